@@ -177,7 +177,6 @@ stackpop
 	RSUB
 
 . stack data
-stack		RESW 1000
 stackptr	WORD 0
 olda 		RESW 1	
 . ---------------------------------
@@ -185,3 +184,6 @@ olda 		RESW 1
 input		WORD X'FA'
 stdout 		WORD X'A1'
 stevilo		WORD 0
+
+. stack data, last so that the variables above stay within reach
+stack		RESW 1000
@@ -0,0 +1,612 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::asm::parser::{is_symbol, parse_line, Statement};
+use crate::asm::source_map::{SourceLine, SourceMap};
+use crate::errors::{AsmError, LoadError};
+use crate::machine::machine::Machine;
+use crate::machine::register::Register;
+use crate::object::program::{Modification, ObjectProgram};
+use crate::opcode::opcode::Opcode;
+
+///
+/// A symbol defined in the source. Labels are relative to the program start,
+/// while `EQU` constants may be absolute.
+///
+#[derive(Debug, Clone, Copy)]
+pub struct Symbol {
+    pub value: u32,
+    pub relative: bool,
+}
+
+/// Result of evaluating an operand expression.
+/// External references are added by the loader, marked negative when subtracted.
+struct Value {
+    value: i64,
+    relative: bool,
+    externals: Vec<(bool, String)>,
+}
+
+impl Value {
+    /// Adds the modification records needed to relocate the value at the address.
+    fn modifications(&self, program: &mut ObjectProgram, address: u32, half_bytes: u8) {
+        if self.relative {
+            program.modifications.push(Modification::relocation(address, half_bytes));
+        }
+        for (negative, symbol) in &self.externals {
+            program.modifications.push(Modification {
+                address,
+                half_bytes,
+                symbol: Some(symbol.clone()),
+                negative: *negative,
+            });
+        }
+    }
+}
+
+enum Addressing {
+    Simple,
+    Immediate,
+    Indirect,
+}
+
+/// Assembles the source into an object program.
+pub fn assemble(source: &str) -> Result<ObjectProgram, AsmError> {
+    let mut assembler = Assembler::default();
+    assembler.first_pass(source)?;
+    let mut program = assembler.second_pass()?;
+
+    if let Some(map) = &mut program.source {
+        map.text = source.lines().map(str::to_string).collect();
+    }
+    Ok(program)
+}
+
+/// Assembles the `.asm` file at the given path and writes the `.obj` file next to it,
+/// along with a `.sym` file holding the labels and a `.lst` listing.
+/// Existing files with those names are replaced. Gives the program and the paths written.
+pub fn assemble_file(path: &Path) -> Result<(ObjectProgram, Vec<PathBuf>), LoadError> {
+    let source = fs::read_to_string(path)?;
+    let mut program = assemble(&source)?;
+    let mut files = vec![
+        (path.with_extension("obj"), program.to_string()),
+        (path.with_extension("sym"), program.labels.to_string()),
+    ];
+    if let Some(map) = &program.source {
+        files.push((path.with_extension("lst"), map.listing(|address| program.byte_at(address))));
+    }
+    for (file, contents) in &files {
+        fs::write(file, contents).map_err(|e| LoadError::Io(format!("{}: {e}", file.display())))?;
+    }
+
+    if let Some(map) = &mut program.source {
+        map.path = Some(path.to_path_buf());
+    }
+
+    Ok((program, files.into_iter().map(|(file, _)| file).collect()))
+}
+
+#[derive(Default)]
+struct Assembler {
+    symbols: BTreeMap<String, Symbol>,
+    /// Statements with their address and size
+    statements: Vec<(Statement, u32, u32)>,
+    name: String,
+    start: u32,
+    length: u32,
+    entry: Option<(usize, String)>,
+    base: Option<u32>,
+    definitions: Vec<(usize, String)>,
+    references: Vec<String>,
+}
+
+impl Assembler {
+    /// Defines symbols and assigns addresses to statements.
+    fn first_pass(&mut self, source: &str) -> Result<(), AsmError> {
+        let mut locctr = 0;
+        let mut started = false;
+
+        for (ix, text) in source.lines().enumerate() {
+            let Some(statement) = parse_line(ix + 1, text)? else {
+                continue;
+            };
+            let line = statement.line;
+            let mnemonic = statement.mnemonic.as_deref().unwrap_or("");
+
+            if mnemonic == "START" {
+                if started {
+                    return Err(AsmError { line, message: "START must be the first statement.".into() });
+                }
+                let operand = statement.operand.as_deref().unwrap_or("0");
+                locctr = u32::from_str_radix(operand, 16).map_err(|_| AsmError {
+                    line,
+                    message: format!("Invalid start address '{operand}'."),
+                })?;
+                self.start = locctr;
+                self.name = statement.label.clone().unwrap_or_default();
+            }
+            started = true;
+
+            if let Some(label) = &statement.label {
+                let symbol = if mnemonic == "EQU" {
+                    let value = self.eval(statement.operand.as_deref(), locctr, line)?;
+                    if !value.externals.is_empty() {
+                        return Err(AsmError { line, message: "External reference not allowed in EQU.".into() });
+                    }
+                    Symbol { value: value.value as u32, relative: value.relative }
+                } else {
+                    Symbol { value: locctr, relative: true }
+                };
+                if self.symbols.insert(label.clone(), symbol).is_some() || self.references.contains(label) {
+                    return Err(AsmError { line, message: format!("Duplicate symbol '{label}'.") });
+                }
+            }
+
+            let size = match mnemonic {
+                "" | "START" | "EQU" | "BASE" | "NOBASE" => 0,
+                "END" => {
+                    if self.entry.is_some() {
+                        return Err(AsmError { line, message: "Duplicate END.".into() });
+                    }
+                    let operand = statement.operand.clone().unwrap_or_default();
+                    self.entry = Some((line, operand));
+                    0
+                }
+                "EXTDEF" => {
+                    for name in external_names(statement.operand.as_deref(), line)? {
+                        self.definitions.push((line, name));
+                    }
+                    0
+                }
+                "EXTREF" => {
+                    for name in external_names(statement.operand.as_deref(), line)? {
+                        if self.symbols.contains_key(&name) || self.references.contains(&name) {
+                            return Err(AsmError { line, message: format!("Duplicate symbol '{name}'.") });
+                        }
+                        self.references.push(name);
+                    }
+                    0
+                }
+                "WORD" => 3,
+                "BYTE" => parse_byte_literal(statement.operand.as_deref(), line)?.len() as u32,
+                "RESB" => self.eval_absolute(statement.operand.as_deref(), locctr, line)?,
+                "RESW" => 3 * self.eval_absolute(statement.operand.as_deref(), locctr, line)?,
+                _ => match Opcode::from_mnemonic(mnemonic) {
+                    Some((_, 3)) if statement.extended => 4,
+                    Some((_, format)) if !statement.extended => format as u32,
+                    Some(_) => return Err(AsmError { line, message: format!("{mnemonic} has no extended format.") }),
+                    None => return Err(AsmError { line, message: format!("Unknown mnemonic '{mnemonic}'.") }),
+                },
+            };
+
+            self.statements.push((statement, locctr, size));
+            locctr += size;
+            if locctr > Machine::MAX_ADDRESS {
+                return Err(AsmError { line, message: "Program exceeds memory size.".into() });
+            }
+            self.length = self.length.max(locctr - self.start);
+        }
+
+        Ok(())
+    }
+
+    /// Generates the object code of each statement.
+    fn second_pass(&mut self) -> Result<ObjectProgram, AsmError> {
+        let mut program = ObjectProgram {
+            name: self.name.clone(),
+            start: self.start,
+            length: self.length,
+            references: self.references.clone(),
+            ..Default::default()
+        };
+
+        for (line, name) in &self.definitions {
+            match self.symbols.get(name) {
+                Some(symbol) if symbol.relative => program.definitions.push((name.clone(), symbol.value)),
+                _ => return Err(AsmError { line: *line, message: format!("'{name}' is not a label in this program.") }),
+            }
+        }
+
+        let mut source = SourceMap::default();
+        let statements = std::mem::take(&mut self.statements);
+        for (statement, address, size) in &statements {
+            let bytes = self.generate(statement, *address, &mut program)?;
+            for (ix, byte) in bytes.into_iter().enumerate() {
+                program.push_byte(address + ix as u32, byte);
+            }
+
+            if *size > 0 {
+                let mnemonic = statement.mnemonic.as_deref().unwrap_or("");
+                source.lines.push(SourceLine {
+                    line: statement.line,
+                    address: *address,
+                    length: *size,
+                    instruction: Opcode::from_mnemonic(mnemonic).is_some(),
+                });
+            }
+        }
+        program.source = Some(source);
+
+        // The program name is added as section name when loaded
+        for (name, symbol) in &self.symbols {
+            if symbol.relative && *name != self.name {
+                program.labels.insert(name, symbol.value);
+            }
+        }
+
+        if let Some((line, operand)) = &self.entry {
+            if !operand.is_empty() {
+                program.entry = Some(self.eval_address(Some(operand), self.start, *line)?);
+            }
+        }
+
+        Ok(program)
+    }
+
+    /// Generates the bytes of a single statement.
+    fn generate(&mut self, statement: &Statement, address: u32, program: &mut ObjectProgram) -> Result<Vec<u8>, AsmError> {
+        let line = statement.line;
+        let operand = statement.operand.as_deref();
+        let mnemonic = statement.mnemonic.as_deref().unwrap_or("");
+
+        match mnemonic {
+            "BASE" => {
+                self.base = Some(self.eval_address(operand, address, line)?);
+                Ok(vec![])
+            }
+            "NOBASE" => {
+                self.base = None;
+                Ok(vec![])
+            }
+            "WORD" => {
+                let value = self.eval(operand, address, line)?;
+                if !(-0x80_0000..=0xFF_FFFF).contains(&value.value) {
+                    return Err(AsmError { line, message: "Value does not fit into a word.".into() });
+                }
+                value.modifications(program, address, 6);
+                let value = value.value as u32;
+                Ok(vec![(value >> 16) as u8, (value >> 8) as u8, value as u8])
+            }
+            "BYTE" => parse_byte_literal(operand, line),
+            "" | "START" | "END" | "EQU" | "RESB" | "RESW" | "EXTDEF" | "EXTREF" => Ok(vec![]),
+            _ => {
+                let (opcode, format) = Opcode::from_mnemonic(mnemonic).unwrap_or_default();
+                match format {
+                    1 => Ok(vec![opcode]),
+                    2 => self.generate_format2(mnemonic, opcode, operand, address, line),
+                    _ => self.generate_format34(statement, opcode, address, program),
+                }
+            }
+        }
+    }
+
+    fn generate_format2(&self, mnemonic: &str, opcode: u8, operand: Option<&str>, address: u32, line: usize) -> Result<Vec<u8>, AsmError> {
+        let operand = operand.unwrap_or("");
+        let args = operand.split(',').map(str::trim).filter(|a| !a.is_empty()).collect::<Vec<_>>();
+
+        let register = |name: &str| {
+            Register::from_name(name)
+                .map(|r| r.1 as u32)
+                .ok_or(AsmError { line, message: format!("Invalid register '{name}'.") })
+        };
+
+        let (r1, r2) = match (mnemonic, args.as_slice()) {
+            ("CLEAR" | "TIXR", [r1]) => (register(r1)?, 0),
+            ("SVC", [n]) => (self.eval_absolute(Some(n), address, line)?, 0),
+            ("SHIFTL" | "SHIFTR", [r1, n]) => {
+                let n = self.eval_absolute(Some(n), address, line)?;
+                if !(1..=16).contains(&n) {
+                    return Err(AsmError { line, message: format!("Invalid shift count {n}.") });
+                }
+                (register(r1)?, n - 1)
+            }
+            ("CLEAR" | "TIXR" | "SVC" | "SHIFTL" | "SHIFTR", _) => {
+                return Err(AsmError { line, message: format!("Invalid operands '{operand}' for {mnemonic}.") });
+            }
+            (_, [r1, r2]) => (register(r1)?, register(r2)?),
+            _ => return Err(AsmError { line, message: format!("{mnemonic} expects two registers.") }),
+        };
+
+        if r1 > 0xF {
+            return Err(AsmError { line, message: format!("Operand '{operand}' too large.") });
+        }
+
+        Ok(vec![opcode, (r1 << 4 | r2) as u8])
+    }
+
+    fn generate_format34(&self, statement: &Statement, opcode: u8, address: u32, program: &mut ObjectProgram) -> Result<Vec<u8>, AsmError> {
+        let line = statement.line;
+        let Some(operand) = statement.operand.as_deref() else {
+            // RSUB and similar
+            return Ok(if statement.extended {
+                vec![opcode | 0b11, 0x10, 0, 0]
+            } else {
+                vec![opcode | 0b11, 0, 0]
+            });
+        };
+
+        let (addressing, operand) = if let Some(operand) = operand.strip_prefix('#') {
+            (Addressing::Immediate, operand)
+        } else if let Some(operand) = operand.strip_prefix('@') {
+            (Addressing::Indirect, operand)
+        } else {
+            (Addressing::Simple, operand)
+        };
+
+        let (operand, indexed) = match operand.rsplit_once(',') {
+            Some((expr, index)) if index.trim().eq_ignore_ascii_case("X") => (expr.trim(), true),
+            Some(_) => return Err(AsmError { line, message: "Only X can be used for indexing.".into() }),
+            None => (operand, false),
+        };
+
+        let ni = match addressing {
+            Addressing::Simple => 0b11,
+            Addressing::Immediate => 0b01,
+            Addressing::Indirect => 0b10,
+        };
+        let x = if indexed { 0b1000 } else { 0 };
+        let target = self.eval(Some(operand), address, line)?;
+
+        if statement.extended {
+            if !(0..=0xF_FFFF).contains(&target.value) {
+                return Err(AsmError { line, message: format!("Address {:X} out of range.", target.value) });
+            }
+            target.modifications(program, address + 1, 5);
+            let value = target.value as u32;
+            return Ok(vec![opcode | ni, (x | 0b0001) << 4 | (value >> 16) as u8 & 0xF, (value >> 8) as u8, value as u8]);
+        }
+
+        if !target.externals.is_empty() {
+            return Err(AsmError { line, message: format!("External reference in '{operand}' requires extended format.") });
+        }
+
+        // Only addresses within the program may be relative to PC or base
+        let (bp, disp) = if !target.relative {
+            if !(0..=0xFFF).contains(&target.value) {
+                return Err(AsmError { line, message: format!("Value {} out of range, use extended format (+).", target.value) });
+            }
+            (0b00, target.value as u32)
+        } else if (-2048..=2047).contains(&(target.value - (address as i64 + 3))) {
+            (0b01, (target.value - (address as i64 + 3)) as u32 & 0xFFF)
+        } else if let Some(disp) = self.base
+            .map(|base| target.value - base as i64)
+            .filter(|disp| (0..=0xFFF).contains(disp)) {
+            (0b10, disp as u32)
+        } else {
+            return Err(AsmError { line, message: format!("Address of '{operand}' out of range, use extended format (+).") });
+        };
+
+        Ok(vec![opcode | ni, (x | bp << 1) << 4 | (disp >> 8) as u8, disp as u8])
+    }
+
+    /// Evaluates an expression of symbols and numbers joined by `+` and `-`.
+    /// `*` stands for the address of the current statement.
+    fn eval(&self, expr: Option<&str>, locctr: u32, line: usize) -> Result<Value, AsmError> {
+        let expr = expr.map(str::trim).unwrap_or("");
+        if expr.is_empty() {
+            return Err(AsmError { line, message: "Missing operand.".into() });
+        }
+
+        let mut value = 0i64;
+        let mut relative = 0i64;
+        let mut externals = Vec::new();
+        let mut sign = 1;
+        let mut rest = expr;
+
+        loop {
+            rest = rest.trim_start();
+            if let Some(r) = rest.strip_prefix('-') {
+                sign = -sign;
+                rest = r;
+                continue;
+            } else if let Some(r) = rest.strip_prefix('+') {
+                rest = r;
+                continue;
+            }
+
+            let end = rest.find(['+', '-']).unwrap_or(rest.len());
+            let term = rest[..end].trim();
+
+            let (term_value, term_relative) = if term == "*" {
+                (locctr as i64, true)
+            } else if let Ok(number) = term.parse::<i64>() {
+                (number, false)
+            } else if let Some(hex) = term.strip_prefix("X'").and_then(|t| t.strip_suffix('\'')) {
+                let number = i64::from_str_radix(hex, 16)
+                    .map_err(|_| AsmError { line, message: format!("Invalid hex constant '{term}'.") })?;
+                (number, false)
+            } else if self.references.iter().any(|name| name == term) {
+                externals.push((sign < 0, term.to_string()));
+                (0, false)
+            } else if is_symbol(term) {
+                let symbol = self.symbols.get(term)
+                    .ok_or(AsmError { line, message: format!("Undefined symbol '{term}'.") })?;
+                (symbol.value as i64, symbol.relative)
+            } else {
+                return Err(AsmError { line, message: format!("Invalid expression '{expr}'.") });
+            };
+
+            value += sign * term_value;
+            if term_relative {
+                relative += sign;
+            }
+
+            if end == rest.len() {
+                break;
+            }
+            sign = if &rest[end..=end] == "-" { -1 } else { 1 };
+            rest = &rest[end + 1..];
+        }
+
+        match relative {
+            0 => Ok(Value { value, relative: false, externals }),
+            1 => Ok(Value { value, relative: true, externals }),
+            _ => Err(AsmError { line, message: format!("Expression '{expr}' is not relocatable.") }),
+        }
+    }
+
+    /// Evaluates an expression which must be a non-negative absolute value.
+    fn eval_absolute(&self, expr: Option<&str>, locctr: u32, line: usize) -> Result<u32, AsmError> {
+        let value = self.eval(expr, locctr, line)?;
+        if value.relative || !value.externals.is_empty() || value.value < 0 || value.value > Machine::MAX_ADDRESS as i64 {
+            return Err(AsmError { line, message: format!("Expected a positive number, found '{}'.", expr.unwrap_or("")) });
+        }
+        Ok(value.value as u32)
+    }
+
+    /// Evaluates an expression which must be a valid address.
+    fn eval_address(&self, expr: Option<&str>, locctr: u32, line: usize) -> Result<u32, AsmError> {
+        let value = self.eval(expr, locctr, line)?;
+        if !value.externals.is_empty() {
+            return Err(AsmError { line, message: "External reference not allowed here.".into() });
+        }
+        if !(0..=Machine::MAX_ADDRESS as i64).contains(&value.value) {
+            return Err(AsmError { line, message: format!("Address {:X} out of range.", value.value) });
+        }
+        Ok(value.value as u32)
+    }
+}
+
+/// Parses the comma-separated symbol names of `EXTDEF` and `EXTREF`.
+fn external_names(operand: Option<&str>, line: usize) -> Result<Vec<String>, AsmError> {
+    operand
+        .unwrap_or("")
+        .split(',')
+        .map(|name| {
+            let name = name.trim();
+            if is_symbol(name) && name.len() <= 6 {
+                Ok(name.to_string())
+            } else {
+                Err(AsmError { line, message: format!("Invalid external symbol '{name}', names are limited to 6 characters.") })
+            }
+        })
+        .collect()
+}
+
+/// Parses `C'...'` and `X'...'` literals and small decimal numbers.
+fn parse_byte_literal(operand: Option<&str>, line: usize) -> Result<Vec<u8>, AsmError> {
+    let operand = operand.unwrap_or("");
+    let error = || AsmError { line, message: format!("Invalid byte constant '{operand}'.") };
+
+    if let Some(chars) = operand.strip_prefix("C'").and_then(|o| o.strip_suffix('\'')) {
+        Ok(chars.bytes().collect())
+    } else if let Some(hex) = operand.strip_prefix("X'").and_then(|o| o.strip_suffix('\'')) {
+        if hex.len() % 2 != 0 {
+            return Err(error());
+        }
+        (0..hex.len())
+            .step_by(2)
+            .map(|ix| u8::from_str_radix(&hex[ix..ix + 2], 16).map_err(|_| error()))
+            .collect()
+    } else {
+        operand.parse::<u8>().map(|byte| vec![byte]).map_err(|_| error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::source_map::SourceMap;
+    use crate::loader::loader::load;
+    use crate::loader::symbols::SymbolTable;
+    use crate::object::parser::parse_object;
+
+    const PROGRAM: &str = "\
+PROG   START 0
+       LDA   FIVE
+       +LDB  #TABLE
+       STA   RESULT
+halt   J     halt
+FIVE   WORD  5
+TABLE  WORD  TABLE
+RESULT RESW  1
+       END   PROG
+";
+
+    #[test]
+    fn object_symbols_and_listing_round_trip() {
+        let program = assemble(PROGRAM).unwrap();
+        let object = program.to_string();
+        let parsed = parse_object(&object).unwrap();
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].to_string(), object);
+
+        let labels = SymbolTable::parse(&program.labels.to_string()).unwrap();
+        assert_eq!(labels, program.labels);
+        assert_eq!(labels.address("TABLE"), Some(0x10));
+
+        let source = program.source.as_ref().unwrap();
+        let listing = SourceMap::parse_listing(&source.listing(|address| program.byte_at(address))).unwrap();
+        assert_eq!(listing.lines, source.lines);
+        assert_eq!(listing.text, source.text);
+    }
+
+    #[test]
+    fn loads_assembled_program_at_another_address() {
+        let program = assemble(PROGRAM).unwrap();
+        let mut machine = Machine::new();
+        let map = load(&mut machine, &[program], Some(0x1000)).unwrap();
+
+        assert_eq!(map.entry, 0x1000);
+        assert_eq!(map.sections[0].labels.address("halt"), Some(0x100A));
+        // PC-relative LDA is left alone, the format 4 address and the word are relocated
+        assert_eq!(machine.read_word(0x1000).unwrap(), 0x03200A);
+        assert_eq!(machine.read_word(0x1004).unwrap() & 0xFFFFF, 0x1010);
+        assert_eq!(machine.read_word(0x1010).unwrap(), 0x1010);
+    }
+
+    #[test]
+    fn links_external_references() {
+        let main = assemble("MAIN   START 0\n       EXTREF VALUE\n       +LDA  VALUE\nPTR    WORD  VALUE+3\n       END   MAIN\n").unwrap();
+        let data = assemble("DATA   START 0\n       EXTDEF VALUE\n       RESB  6\nVALUE  WORD  7\n       END\n").unwrap();
+        let programs = parse_object(&format!("{main}{data}")).unwrap();
+        let mut machine = Machine::new();
+        let map = load(&mut machine, &programs, Some(0x200)).unwrap();
+
+        assert_eq!(map.symbols["DATA"], 0x207);
+        assert_eq!(map.symbols["VALUE"], 0x20D);
+        assert_eq!(machine.read_word(0x201).unwrap() & 0xFFFFF, 0x20D);
+        assert_eq!(machine.read_word(0x204).unwrap(), 0x210);
+        assert_eq!(machine.read_word(0x20D).unwrap(), 7);
+    }
+
+    #[test]
+    fn uses_direct_addressing_only_for_absolute_values() {
+        let program = assemble("P      START 1000
+       LDA   #5
+       +LDA  #5000
+       END
+").unwrap();
+        assert_eq!(program.text[0].bytes, [0x01, 0x00, 0x05, 0x01, 0x10, 0x13, 0x88]);
+        assert!(program.modifications.is_empty());
+
+        for operand in ["#-1", "#5000", "5000"] {
+            let error = assemble(&format!("P      START 5000
+       LDA   {operand}
+       END
+")).unwrap_err();
+            assert_eq!(error.line, 2, "{operand}");
+            assert!(error.message.contains("use extended format"), "{operand}: {}", error.message);
+        }
+    }
+
+    #[test]
+    fn rejects_relative_address_out_of_reach() {
+        let error = assemble("P      START 0
+       LDA   FAR
+       RESB  4096
+FAR    WORD  1
+       END
+").unwrap_err();
+        assert_eq!(error.line, 2);
+
+        let program = assemble("P      START 0
+       BASE  FAR
+       LDA   FAR
+       RESB  4096
+FAR    WORD  1
+       END
+").unwrap();
+        assert_eq!(program.text[0].bytes, [0x03, 0x40, 0x00]);
+    }
+}
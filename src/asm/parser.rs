@@ -0,0 +1,112 @@
+use crate::errors::AsmError;
+use crate::opcode::opcode::Opcode;
+
+///
+/// A single line of assembly source, split into its fields.
+/// Lines holding only a label still produce a statement, so the label gets defined.
+///
+#[derive(Debug)]
+pub struct Statement {
+    pub line: usize,
+    pub label: Option<String>,
+    pub mnemonic: Option<String>,
+    pub extended: bool,
+    pub operand: Option<String>,
+}
+
+/// Mnemonics (apart from format 1 instructions) which never take an operand.
+const NO_OPERAND: [&str; 2] = ["RSUB", "NOBASE"];
+
+/// Parses a line of source. Returns `None` for empty and comment-only lines.
+pub fn parse_line(line: usize, text: &str) -> Result<Option<Statement>, AsmError> {
+    let text = text.trim_end();
+    let trimmed = text.trim_start();
+    if trimmed.is_empty() || trimmed.starts_with('.') {
+        return Ok(None);
+    }
+
+    let mut rest = text;
+    let mut label = None;
+
+    // Labels start in the first column
+    if !text.starts_with(char::is_whitespace) {
+        let (token, remaining) = split_token(text);
+        if !is_symbol(token) {
+            return Err(AsmError { line, message: format!("Invalid label '{token}'.") });
+        }
+        label = Some(token.to_string());
+        rest = remaining;
+    }
+
+    let rest = rest.trim_start();
+    if rest.is_empty() || rest.starts_with('.') {
+        return Ok(Some(Statement { line, label, mnemonic: None, extended: false, operand: None }));
+    }
+
+    let (token, rest) = split_token(rest);
+    let (extended, mnemonic) = match token.strip_prefix('+') {
+        Some(mnemonic) => (true, mnemonic.to_ascii_uppercase()),
+        None => (false, token.to_ascii_uppercase()),
+    };
+
+    let takes_operand = !NO_OPERAND.contains(&mnemonic.as_str())
+        && !matches!(Opcode::from_mnemonic(&mnemonic), Some((_, 1)));
+
+    let operand = if takes_operand {
+        parse_operand(line, rest)?
+    } else {
+        None
+    };
+
+    Ok(Some(Statement { line, label, mnemonic: Some(mnemonic), extended, operand }))
+}
+
+/// Reads the operand field. Whitespace is allowed around commas and inside quotes,
+/// anything after the operand is a comment.
+fn parse_operand(line: usize, text: &str) -> Result<Option<String>, AsmError> {
+    let text = text.trim_start();
+    if text.is_empty() || text.starts_with('.') {
+        return Ok(None);
+    }
+
+    let mut operand = String::new();
+    let mut in_quote = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == '\'' {
+            in_quote = !in_quote;
+        } else if c.is_whitespace() && !in_quote {
+            // Skip to the next non-whitespace character
+            while chars.peek().is_some_and(|c| c.is_whitespace()) {
+                chars.next();
+            }
+            if operand.ends_with(',') || chars.peek() == Some(&',') {
+                continue;
+            }
+            break;
+        }
+        operand.push(c);
+    }
+
+    if in_quote {
+        return Err(AsmError { line, message: "Unterminated quote in operand.".into() });
+    }
+
+    Ok(Some(operand))
+}
+
+/// Splits off the first whitespace-delimited token.
+fn split_token(text: &str) -> (&str, &str) {
+    match text.find(char::is_whitespace) {
+        Some(ix) => (&text[..ix], &text[ix..]),
+        None => (text, ""),
+    }
+}
+
+/// Checks whether the token is a valid symbol name.
+pub fn is_symbol(token: &str) -> bool {
+    let mut chars = token.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
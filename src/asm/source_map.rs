@@ -0,0 +1,115 @@
+use std::path::PathBuf;
+use crate::errors::LoadError;
+
+///
+/// Statement of the source and the bytes it occupies.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceLine {
+    /// Line number, starting at 1
+    pub line: usize,
+    pub address: u32,
+    pub length: u32,
+    /// Whether the statement is an instruction rather than data (`WORD`, `BYTE`, `RESW`, `RESB`).
+    pub instruction: bool,
+}
+
+///
+/// Maps the statements of an assembled program to their addresses.
+/// Addresses are relative to the program start until it is loaded.
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SourceMap {
+    pub path: Option<PathBuf>,
+    /// Source lines, without line endings
+    pub text: Vec<String>,
+    /// Statements that occupy memory, in source order
+    pub lines: Vec<SourceLine>,
+}
+
+impl SourceMap {
+    /// Finds the statement occupying the address.
+    pub fn line_at(&self, address: u32) -> Option<&SourceLine> {
+        self.lines
+            .iter()
+            .find(|line| (line.address..line.address + line.length).contains(&address))
+    }
+
+    /// Finds the statement on the given line.
+    pub fn line(&self, line: usize) -> Option<&SourceLine> {
+        self.lines.iter().find(|source_line| source_line.line == line)
+    }
+
+    /// Source text of the line, starting at 1.
+    pub fn text(&self, line: usize) -> Option<&str> {
+        self.text.get(line.checked_sub(1)?).map(String::as_str)
+    }
+
+    /// Formats a listing of the source, with the address, length and first code bytes of each statement.
+    /// Statements are marked `I` for instructions and `D` for data.
+    pub fn listing(&self, code: impl Fn(u32) -> Option<u8>) -> String {
+        let mut listing = String::new();
+        for (index, text) in self.text.iter().enumerate() {
+            let prefix = match self.line(index + 1) {
+                Some(line) => {
+                    let bytes = (line.address..line.address + line.length.min(4))
+                        .filter_map(&code)
+                        .map(|byte| format!("{byte:02X}"))
+                        .collect::<String>();
+                    let kind = if line.instruction { 'I' } else { 'D' };
+                    format!("{:>5} {:06X} {:>6X} {bytes:<8} {kind}", line.line, line.address, line.length)
+                }
+                None => format!("{:>5}", index + 1),
+            };
+            listing.push_str(&format!("{prefix:<32}| {text}\n"));
+        }
+        listing
+    }
+
+    /// Parses a listing written by [`Self::listing`].
+    pub fn parse_listing(listing: &str) -> Result<Self, LoadError> {
+        let mut map = Self::default();
+        for (index, row) in listing.lines().enumerate() {
+            let error = |message: &str| LoadError::Listing { line: index + 1, message: message.to_string() };
+            let (prefix, text) = row.split_once('|').ok_or(error("Missing '|' before the source."))?;
+            let text = text.strip_prefix(' ').unwrap_or(text);
+
+            let fields = prefix.split_whitespace().collect::<Vec<_>>();
+            let line = fields
+                .first()
+                .and_then(|line| line.parse::<usize>().ok())
+                .ok_or(error("Invalid line number."))?;
+            if line != map.text.len() + 1 {
+                return Err(error("Lines are out of order."));
+            }
+            map.text.push(text.to_string());
+
+            if fields.len() > 1 {
+                let hex = |field: &str| u32::from_str_radix(field, 16).map_err(|_| error(&format!("Invalid number '{field}'.")));
+                let (Some(address), Some(length), Some(kind)) = (fields.get(1), fields.get(2), fields.last()) else {
+                    return Err(error("Expected address, length and kind."));
+                };
+                map.lines.push(SourceLine {
+                    line,
+                    address: hex(address)?,
+                    length: hex(length)?,
+                    instruction: match *kind {
+                        "I" => true,
+                        "D" => false,
+                        _ => return Err(error(&format!("Invalid kind '{kind}'."))),
+                    },
+                });
+            }
+        }
+        Ok(map)
+    }
+
+    /// Moves all addresses by `delta`, wrapping around like the loader does.
+    pub fn relocated(&self, delta: u32) -> Self {
+        let mut map = self.clone();
+        for line in &mut map.lines {
+            line.address = line.address.wrapping_add(delta);
+        }
+        map
+    }
+}
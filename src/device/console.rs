@@ -0,0 +1,118 @@
+use std::cell::RefCell;
+use std::io;
+use std::io::{ErrorKind, Write};
+use std::rc::Rc;
+use crate::device::device_trait::Device;
+
+///
+/// Output of console devices, shared with the user interface that shows it.
+///
+#[derive(Debug, Clone, Default)]
+pub struct ConsoleBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl ConsoleBuffer {
+    pub fn push(&self, byte: u8) {
+        self.0.borrow_mut().push(byte);
+    }
+
+    /// Copy of the bytes written so far.
+    pub fn bytes(&self) -> Vec<u8> {
+        self.0.borrow().clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.borrow().is_empty()
+    }
+
+    pub fn clear(&self) {
+        self.0.borrow_mut().clear();
+    }
+
+    /// Splits the output into lines. Control characters are shown in caret notation (`^[`)
+    /// instead of being interpreted, so escape sequences cannot disturb the terminal.
+    /// Tabs are expanded to 8 columns and bytes above 0x7F are shown as `<NN>`.
+    pub fn text_lines(&self) -> Vec<String> {
+        let bytes = self.0.borrow();
+        let mut lines = vec![String::new()];
+        let mut bytes = bytes.iter().peekable();
+        while let Some(&byte) = bytes.next() {
+            let line = lines.last_mut().expect("there is always a line");
+            match byte {
+                b'\n' => lines.push(String::new()),
+                b'\r' if bytes.peek() == Some(&&b'\n') => {}
+                b'\t' => line.push_str(&" ".repeat(8 - line.chars().count() % 8)),
+                0x00..=0x1F | 0x7F => {
+                    line.push('^');
+                    line.push((byte ^ 0x40) as char);
+                }
+                0x80..=0xFF => line.push_str(&format!("<{byte:02X}>")),
+                _ => line.push(byte as char),
+            }
+        }
+        if lines.last().is_some_and(String::is_empty) {
+            lines.pop();
+        }
+        lines
+    }
+
+    /// Formats the output as a hex dump with `width` bytes per line, each line starting with
+    /// the offset and ending with the printable characters.
+    pub fn hex_lines(&self, width: usize) -> Vec<String> {
+        let width = width.max(1);
+        self.0
+            .borrow()
+            .chunks(width)
+            .enumerate()
+            .map(|(index, chunk)| {
+                let hex = chunk.iter().map(|byte| format!("{byte:02X}")).collect::<Vec<_>>().join(" ");
+                let text = chunk
+                    .iter()
+                    .map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' })
+                    .collect::<String>();
+                format!("{:06X}  {hex:<width$}  {text}", index * width, width = width * 3 - 1)
+            })
+            .collect()
+    }
+}
+
+///
+/// Output device that collects the written bytes into a buffer instead of a terminal,
+/// optionally also writing them to another stream.
+///
+pub struct ConsoleDevice {
+    buffer: ConsoleBuffer,
+    mirror: Option<Box<dyn Write>>,
+}
+
+impl ConsoleDevice {
+    pub fn new(buffer: ConsoleBuffer) -> Self {
+        Self { buffer, mirror: None }
+    }
+
+    /// Also writes the output to the writer, e.g. stdout in headless mode.
+    pub fn mirrored(buffer: ConsoleBuffer, mirror: impl Write + 'static) -> Self {
+        Self { buffer, mirror: Some(Box::new(mirror)) }
+    }
+}
+
+impl Device for ConsoleDevice {
+    fn test(&self) -> bool {
+        true
+    }
+
+    fn read(&mut self) -> io::Result<u8> {
+        Err(io::Error::new(ErrorKind::Unsupported, "Cannot read from the console"))
+    }
+
+    fn write(&mut self, value: u8) -> io::Result<()> {
+        self.buffer.push(value);
+        match &mut self.mirror {
+            Some(mirror) => mirror.write_all(&[value]),
+            None => Ok(()),
+        }
+    }
+}
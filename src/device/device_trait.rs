@@ -1,7 +1,18 @@
+use std::io;
+
 pub trait Device {
     fn test(&self) -> bool;
 
-    fn read(&mut self) -> u8;
+    fn read(&mut self) -> io::Result<u8>;
+
+    fn write(&mut self, value: u8) -> io::Result<()>;
+
+    /// Position in the underlying stream, for devices that can seek.
+    fn position(&self) -> Option<u64> {
+        None
+    }
 
-    fn write(&mut self, value: u8);
+    fn seek(&mut self, _position: u64) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "Device cannot seek"))
+    }
 }
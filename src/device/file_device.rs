@@ -1,5 +1,7 @@
-use std::fs::File;
-use std::io::{Read, Write};
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
 use crate::device::device_trait::Device;
 
 pub struct FileDevice {
@@ -12,33 +14,66 @@ impl Device for FileDevice {
         self.file.metadata().is_ok()
     }
 
-    fn read(&mut self) -> u8 {
-        // Read the file and return the first byte
+    fn read(&mut self) -> io::Result<u8> {
+        // Read the next byte, or 0 at the end of file
         let mut buffer = [0; 1];
-        self.file.read(&mut buffer).expect(format!("Failed to read file ...").as_str());
-        buffer[0]
+        if self.file.read(&mut buffer)? == 0 {
+            return Ok(0);
+        }
+        Ok(buffer[0])
     }
 
-    fn write(&mut self, value: u8) {
+    fn write(&mut self, value: u8) -> io::Result<()> {
         // Write the byte to the file
-        self.file.write_all(&[value]).expect(format!("Failed to write file ...").as_str());
+        self.file.write_all(&[value])
+    }
+
+    fn position(&self) -> Option<u64> {
+        (&self.file).stream_position().ok()
+    }
+
+    fn seek(&mut self, position: u64) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(position)).map(|_| ())
     }
 }
 
 impl FileDevice {
-    pub fn new(device_addr: u8) -> Self {
-        // Create file if it doesn't exist
-        let file_name = format!("{:02X}.dev", device_addr);
-        let exists = std::path::Path::new(&file_name).exists();
-        let file = std::fs::OpenOptions::new()
+    pub fn new(device_addr: u8) -> io::Result<Self> {
+        Self::in_directory(Path::new("."), device_addr)
+    }
+
+    /// Opens `{device_addr:02X}.dev` in the directory.
+    pub fn in_directory(directory: &Path, device_addr: u8) -> io::Result<Self> {
+        Self::open(&directory.join(format!("{:02X}.dev", device_addr)))
+    }
+
+    /// Opens the file for reading and writing, creating it if it doesn't exist.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new()
             .read(true)
             .write(true)
-            .create(!exists)
-            .open(&file_name)
-            .expect(format!("Failed to create device file {device_addr}.").as_str());
+            .create(true)
+            .truncate(false)
+            .open(path)?;
 
-        Self {
+        Ok(Self {
             file,
-        }
+        })
+    }
+
+    /// Opens an existing file for reading only.
+    pub fn input(path: &Path) -> io::Result<Self> {
+        Ok(Self { file: File::open(path)? })
+    }
+
+    /// Creates the file for writing only, truncating it unless appending.
+    pub fn output(path: &Path, append: bool) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(append)
+            .truncate(!append)
+            .open(path)?;
+        Ok(Self { file })
     }
 }
@@ -0,0 +1,62 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io;
+use std::io::ErrorKind;
+use std::rc::Rc;
+use crate::device::device_trait::Device;
+
+///
+/// Keys waiting to be read by the program, shared between the user interface and the keyboard device.
+///
+#[derive(Debug, Clone, Default)]
+pub struct KeyQueue(Rc<RefCell<VecDeque<u8>>>);
+
+impl KeyQueue {
+    pub fn push(&self, key: u8) {
+        self.0.borrow_mut().push_back(key);
+    }
+
+    pub fn pop(&self) -> Option<u8> {
+        self.0.borrow_mut().pop_front()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.borrow().is_empty()
+    }
+
+    pub fn clear(&self) {
+        self.0.borrow_mut().clear();
+    }
+}
+
+///
+/// Reads keys from the queue. `TD` reports whether a key is available,
+/// `RD` without one gives 0.
+///
+pub struct KeyboardDevice {
+    keys: KeyQueue,
+}
+
+impl KeyboardDevice {
+    pub fn new(keys: KeyQueue) -> Self {
+        Self { keys }
+    }
+}
+
+impl Device for KeyboardDevice {
+    fn test(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    fn read(&mut self) -> io::Result<u8> {
+        Ok(self.keys.pop().unwrap_or(0))
+    }
+
+    fn write(&mut self, _value: u8) -> io::Result<()> {
+        Err(io::Error::new(ErrorKind::Unsupported, "Cannot write to the keyboard"))
+    }
+}
@@ -1,3 +1,10 @@
+pub mod console;
 pub mod device_trait;
 pub mod file_device;
+pub mod keyboard;
+pub mod null_device;
+pub mod pipe_device;
+#[cfg(unix)]
+pub mod socket_device;
+pub mod split_device;
 pub mod std_device;
\ No newline at end of file
@@ -0,0 +1,21 @@
+use std::io;
+use crate::device::device_trait::Device;
+
+///
+/// Device that is always ready, reads zeros and discards everything written.
+///
+pub struct NullDevice;
+
+impl Device for NullDevice {
+    fn test(&self) -> bool {
+        true
+    }
+
+    fn read(&mut self) -> io::Result<u8> {
+        Ok(0)
+    }
+
+    fn write(&mut self, _value: u8) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,61 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io;
+use std::rc::Rc;
+use crate::device::device_trait::Device;
+
+///
+/// Bytes in transit, shared by the devices at both ends of a pipe.
+///
+#[derive(Debug, Clone, Default)]
+pub struct Pipe(Rc<RefCell<VecDeque<u8>>>);
+
+impl Pipe {
+    /// Pipe that already holds the bytes.
+    pub fn with_data(data: &[u8]) -> Self {
+        Self(Rc::new(RefCell::new(data.iter().copied().collect())))
+    }
+
+    /// Copy of the bytes waiting to be read.
+    pub fn bytes(&self) -> Vec<u8> {
+        self.0.borrow().iter().copied().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.borrow().is_empty()
+    }
+}
+
+///
+/// End of a pipe: written bytes are queued, reads take the oldest one.
+/// `TD` reports whether a byte is waiting, `RD` without one gives 0.
+/// A pipe of its own works as an in-memory buffer.
+///
+pub struct PipeDevice {
+    pipe: Pipe,
+}
+
+impl PipeDevice {
+    pub fn new(pipe: Pipe) -> Self {
+        Self { pipe }
+    }
+}
+
+impl Device for PipeDevice {
+    fn test(&self) -> bool {
+        !self.pipe.is_empty()
+    }
+
+    fn read(&mut self) -> io::Result<u8> {
+        Ok(self.pipe.0.borrow_mut().pop_front().unwrap_or(0))
+    }
+
+    fn write(&mut self, value: u8) -> io::Result<()> {
+        self.pipe.0.borrow_mut().push_back(value);
+        Ok(())
+    }
+}
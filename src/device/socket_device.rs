@@ -0,0 +1,86 @@
+use std::cell::{Cell, RefCell};
+use std::io;
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use crate::device::device_trait::Device;
+
+///
+/// Connection to a local Unix socket. `TD` reports whether a byte arrived without blocking,
+/// `RD` waits for the next byte and gives 0 once the other side has closed the connection.
+///
+pub struct SocketDevice {
+    stream: UnixStream,
+    /// Byte read ahead while testing
+    pending: Cell<Option<u8>>,
+    closed: Cell<bool>,
+    /// Error of a test, reported by the next read
+    error: RefCell<Option<io::Error>>,
+}
+
+impl SocketDevice {
+    pub fn connect(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            stream: UnixStream::connect(path)?,
+            pending: Cell::new(None),
+            closed: Cell::new(false),
+            error: RefCell::new(None),
+        })
+    }
+
+    fn read_byte(&self) -> io::Result<Option<u8>> {
+        let mut buffer = [0; 1];
+        match (&self.stream).read(&mut buffer)? {
+            0 => {
+                self.closed.set(true);
+                Ok(None)
+            }
+            _ => Ok(Some(buffer[0])),
+        }
+    }
+}
+
+impl Device for SocketDevice {
+    fn test(&self) -> bool {
+        if self.pending.get().is_some() || self.closed.get() {
+            return true;
+        }
+        let result = self
+            .stream
+            .set_nonblocking(true)
+            .and_then(|_| self.read_byte())
+            .and_then(|byte| self.stream.set_nonblocking(false).map(|_| byte));
+        match result {
+            Ok(byte) => {
+                self.pending.set(byte);
+                true
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                let _ = self.stream.set_nonblocking(false);
+                false
+            }
+            Err(e) => {
+                let _ = self.stream.set_nonblocking(false);
+                *self.error.borrow_mut() = Some(e);
+                true
+            }
+        }
+    }
+
+    fn read(&mut self) -> io::Result<u8> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        if let Some(byte) = self.pending.take() {
+            return Ok(byte);
+        }
+        if self.closed.get() {
+            return Ok(0);
+        }
+        Ok(self.read_byte()?.unwrap_or(0))
+    }
+
+    fn write(&mut self, value: u8) -> io::Result<()> {
+        self.stream.write_all(&[value])
+    }
+}
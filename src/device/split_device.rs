@@ -0,0 +1,39 @@
+use std::io;
+use crate::device::device_trait::Device;
+
+///
+/// Reads from one device and writes to another, e.g. separate input and output files.
+/// Testing and seeking use the input.
+///
+pub struct SplitDevice {
+    input: Box<dyn Device>,
+    output: Box<dyn Device>,
+}
+
+impl SplitDevice {
+    pub fn new(input: Box<dyn Device>, output: Box<dyn Device>) -> Self {
+        Self { input, output }
+    }
+}
+
+impl Device for SplitDevice {
+    fn test(&self) -> bool {
+        self.input.test()
+    }
+
+    fn read(&mut self) -> io::Result<u8> {
+        self.input.read()
+    }
+
+    fn write(&mut self, value: u8) -> io::Result<()> {
+        self.output.write(value)
+    }
+
+    fn position(&self) -> Option<u64> {
+        self.input.position()
+    }
+
+    fn seek(&mut self, position: u64) -> io::Result<()> {
+        self.input.seek(position)
+    }
+}
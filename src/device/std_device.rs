@@ -1,5 +1,5 @@
 use std::io;
-use std::io::{Read, Write};
+use std::io::{ErrorKind, Read, Write};
 use crate::device::device_trait::Device;
 
 pub struct StdInDevice;
@@ -9,14 +9,16 @@ impl Device for StdInDevice {
         true
     }
 
-    fn read(&mut self) -> u8 {
+    fn read(&mut self) -> io::Result<u8> {
         let mut buffer = [0; 1];
-        io::stdin().read(&mut buffer).unwrap();
-        buffer[0]
+        if io::stdin().read(&mut buffer)? == 0 {
+            return Ok(0);
+        }
+        Ok(buffer[0])
     }
 
-    fn write(&mut self, _value: u8) {
-        panic!("Cannot write to stdin")
+    fn write(&mut self, _value: u8) -> io::Result<()> {
+        Err(io::Error::new(ErrorKind::Unsupported, "Cannot write to stdin"))
     }
 }
 
@@ -27,12 +29,12 @@ impl Device for StdOutDevice {
         true
     }
 
-    fn read(&mut self) -> u8 {
-        panic!("Cannot read from stdout")
+    fn read(&mut self) -> io::Result<u8> {
+        Err(io::Error::new(ErrorKind::Unsupported, "Cannot read from stdout"))
     }
 
-    fn write(&mut self, value: u8) {
-        io::stdout().write(&[value]).unwrap();
+    fn write(&mut self, value: u8) -> io::Result<()> {
+        io::stdout().write_all(&[value])
     }
 }
 
@@ -42,11 +44,11 @@ impl Device for StdErrDevice {
         true
     }
 
-    fn read(&mut self) -> u8 {
-        panic!("Cannot read from stderr")
+    fn read(&mut self) -> io::Result<u8> {
+        Err(io::Error::new(ErrorKind::Unsupported, "Cannot read from stderr"))
     }
 
-    fn write(&mut self, value: u8) {
-        io::stderr().write(&[value]).unwrap();
+    fn write(&mut self, value: u8) -> io::Result<()> {
+        io::stderr().write_all(&[value])
     }
 }
@@ -1,8 +1,10 @@
+use std::fmt::{Display, Formatter};
+
 pub trait Error {
     fn message(&self) -> String;
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct RegisterError {
     pub index: u8,
 }
@@ -13,8 +15,260 @@ impl Error for RegisterError {
     }
 }
 
-impl From<RegisterError> for String {
+#[derive(Debug, Clone, PartialEq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Error for AsmError {
+    fn message(&self) -> String {
+        format!("Line {}: {}", self.line, self.message)
+    }
+}
+
+///
+/// Malformed record in an object file.
+/// Line and column are 1-based.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl Error for ObjectError {
+    fn message(&self) -> String {
+        format!("Line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+///
+/// Malformed breakpoint condition.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConditionError {
+    pub expression: String,
+    pub message: String,
+}
+
+impl Error for ConditionError {
+    fn message(&self) -> String {
+        format!("Invalid condition '{}': {}", self.expression, self.message)
+    }
+}
+
+///
+/// Fault raised by the machine while executing an instruction.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum MachineError {
+    InvalidOpcode { opcode: u8 },
+    InvalidRegister(RegisterError),
+    MemoryOutOfBounds { address: u32 },
+    DivisionByZero,
+    Device { device: u8, message: String },
+    Unimplemented { mnemonic: &'static str },
+}
+
+impl Error for MachineError {
+    fn message(&self) -> String {
+        match self {
+            MachineError::InvalidOpcode { opcode } => format!("Invalid opcode {opcode:02X}."),
+            MachineError::InvalidRegister(e) => e.message(),
+            MachineError::MemoryOutOfBounds { address } => format!("Address out of bounds: {address:#X}."),
+            MachineError::DivisionByZero => "Division by zero.".to_string(),
+            MachineError::Device { device, message } => format!("Device {device:02X} failed: {message}"),
+            MachineError::Unimplemented { mnemonic } => format!("{mnemonic} is not implemented."),
+        }
+    }
+}
+
+impl From<RegisterError> for MachineError {
     fn from(e: RegisterError) -> Self {
-        format!("Invalid register index {}.", e.index)
+        MachineError::InvalidRegister(e)
+    }
+}
+
+///
+/// Fault of the instruction at `pc`, along with its raw bytes.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExecError {
+    pub pc: u32,
+    pub bytes: Vec<u8>,
+    pub kind: MachineError,
+}
+
+impl Error for ExecError {
+    fn message(&self) -> String {
+        let bytes = self.bytes.iter().map(|b| format!("{b:02X}")).collect::<String>();
+        format!("{} (at {:06X}: {bytes})", self.kind.message(), self.pc)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoadError {
+    Io(String),
+    Asm(AsmError),
+    Object(ObjectError),
+    NoProgram,
+    DuplicateSymbol(String),
+    UndefinedSymbol { symbol: String, section: String },
+    OutOfMemory { section: String },
+    DefinitionOutOfRange { symbol: String, address: u32, section: String },
+    InvalidModification { address: u32, half_bytes: u8, section: String },
+    SymbolFile { line: usize, message: String },
+    Listing { line: usize, message: String },
+}
+
+impl Error for LoadError {
+    fn message(&self) -> String {
+        match self {
+            LoadError::Io(e) => e.clone(),
+            LoadError::Asm(e) => e.message(),
+            LoadError::Object(e) => e.message(),
+            LoadError::NoProgram => "No object program to load.".to_string(),
+            LoadError::DuplicateSymbol(symbol) => format!("Duplicate external symbol {symbol}."),
+            LoadError::UndefinedSymbol { symbol, section } => format!("Undefined external symbol {symbol} in section {section}."),
+            LoadError::OutOfMemory { section } => format!("Section {section} does not fit into memory."),
+            LoadError::DefinitionOutOfRange { symbol, address, section } => {
+                format!("External symbol {symbol} at {address:06X} is outside section {section}.")
+            }
+            LoadError::InvalidModification { address, half_bytes, section } => {
+                format!("Modification of {half_bytes} half-bytes at {address:06X} in section {section} is longer than a word.")
+            }
+            LoadError::SymbolFile { line, message } => format!("Symbol file line {line}: {message}"),
+            LoadError::Listing { line, message } => format!("Listing line {line}: {message}"),
+        }
+    }
+}
+
+impl From<AsmError> for LoadError {
+    fn from(e: AsmError) -> Self {
+        LoadError::Asm(e)
+    }
+}
+
+impl From<ObjectError> for LoadError {
+    fn from(e: ObjectError) -> Self {
+        LoadError::Object(e)
     }
 }
+
+impl From<std::io::Error> for LoadError {
+    fn from(e: std::io::Error) -> Self {
+        LoadError::Io(e.to_string())
+    }
+}
+
+///
+/// Snapshot file that could not be written or read.
+/// Format errors have a 1-based line.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum SnapshotError {
+    Io(String),
+    Format { line: usize, message: String },
+}
+
+impl Error for SnapshotError {
+    fn message(&self) -> String {
+        match self {
+            SnapshotError::Io(e) => e.clone(),
+            SnapshotError::Format { line, message } => format!("Snapshot line {line}: {message}"),
+        }
+    }
+}
+
+impl From<std::io::Error> for SnapshotError {
+    fn from(e: std::io::Error) -> Self {
+        SnapshotError::Io(e.to_string())
+    }
+}
+
+///
+/// Machine configuration that could not be read or whose devices could not be opened.
+/// Format errors have a 1-based line.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    Io(String),
+    Format { line: usize, message: String },
+    Device { device: u8, message: String },
+}
+
+impl Error for ConfigError {
+    fn message(&self) -> String {
+        match self {
+            ConfigError::Io(e) => e.clone(),
+            ConfigError::Format { line, message } => format!("Config line {line}: {message}"),
+            ConfigError::Device { device, message } => format!("Device {device:02X}: {message}"),
+        }
+    }
+}
+
+impl From<std::io::Error> for ConfigError {
+    fn from(e: std::io::Error) -> Self {
+        ConfigError::Io(e.to_string())
+    }
+}
+
+///
+/// Any error the simulator can report.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum SimulatorError {
+    Exec(ExecError),
+    Load(LoadError),
+    Snapshot(SnapshotError),
+    Config(ConfigError),
+}
+
+impl Error for SimulatorError {
+    fn message(&self) -> String {
+        match self {
+            SimulatorError::Exec(e) => e.message(),
+            SimulatorError::Load(e) => e.message(),
+            SimulatorError::Snapshot(e) => e.message(),
+            SimulatorError::Config(e) => e.message(),
+        }
+    }
+}
+
+impl From<ExecError> for SimulatorError {
+    fn from(e: ExecError) -> Self {
+        SimulatorError::Exec(e)
+    }
+}
+
+impl From<LoadError> for SimulatorError {
+    fn from(e: LoadError) -> Self {
+        SimulatorError::Load(e)
+    }
+}
+
+impl From<SnapshotError> for SimulatorError {
+    fn from(e: SnapshotError) -> Self {
+        SimulatorError::Snapshot(e)
+    }
+}
+
+impl From<ConfigError> for SimulatorError {
+    fn from(e: ConfigError) -> Self {
+        SimulatorError::Config(e)
+    }
+}
+
+macro_rules! impl_display {
+    ($($error:ty),*) => {
+        $(impl Display for $error {
+            fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+                f.write_str(&self.message())
+            }
+        })*
+    };
+}
+
+impl_display!(RegisterError, AsmError, ObjectError, ConditionError, MachineError, ExecError, LoadError, SnapshotError, ConfigError, SimulatorError);
@@ -0,0 +1,10 @@
+#![allow(clippy::module_inception, clippy::upper_case_acronyms)]
+
+pub mod asm;
+pub mod errors;
+pub mod device;
+pub mod loader;
+pub mod machine;
+pub mod object;
+pub mod opcode;
+pub mod ui;
@@ -0,0 +1,227 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use crate::asm::source_map::SourceMap;
+use crate::errors::{LoadError, MachineError};
+use crate::loader::symbols::SymbolTable;
+use crate::machine::machine::Machine;
+use crate::object::program::{Modification, ObjectProgram};
+
+///
+/// Where each control section and external symbol ended up in memory.
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LoadMap {
+    pub sections: Vec<LoadedSection>,
+    pub symbols: BTreeMap<String, u32>,
+    pub entry: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadedSection {
+    pub name: String,
+    pub address: u32,
+    pub length: u32,
+    /// Source statements at their loaded addresses, for assembled programs.
+    pub source: Option<SourceMap>,
+    /// Labels of the section at their loaded addresses.
+    pub labels: SymbolTable,
+}
+
+impl Display for LoadMap {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{:<8}{:<8}{:<8}{:<8}", "Section", "Symbol", "Address", "Length")?;
+        for section in &self.sections {
+            writeln!(f, "{:<8}{:<8}{:06X}  {:06X}", section.name, "", section.address, section.length)?;
+            for (name, address) in &self.symbols {
+                let inside = (section.address..section.address + section.length).contains(address);
+                if inside && *name != section.name {
+                    writeln!(f, "{:<8}{:<8}{:06X}", "", name, address)?;
+                }
+            }
+        }
+        write!(f, "Entry point: {:06X}", self.entry)
+    }
+}
+
+///
+/// Links the object programs and loads them one after another, starting at `base`.
+/// Without a base address, programs are loaded at the start address of the first one.
+///
+pub fn load(machine: &mut Machine, programs: &[ObjectProgram], base: Option<u32>) -> Result<LoadMap, LoadError> {
+    let mut map = LoadMap::default();
+    let Some(first) = programs.first() else {
+        return Err(LoadError::NoProgram);
+    };
+
+    // Pass 1 - assign addresses to sections and build the external symbol table
+    let mut address = base.unwrap_or(first.start);
+    for program in programs {
+        let end = address.checked_add(program.length).filter(|end| *end <= Machine::MAX_ADDRESS + 1);
+        let Some(end) = end else {
+            return Err(LoadError::OutOfMemory { section: program.name.clone() });
+        };
+        define(&mut map, &program.name, address)?;
+        for (name, def_address) in &program.definitions {
+            if !(program.start..=program.start + program.length).contains(def_address) {
+                return Err(LoadError::DefinitionOutOfRange {
+                    symbol: name.clone(),
+                    address: *def_address,
+                    section: program.name.clone(),
+                });
+            }
+            define(&mut map, name, address + (def_address - program.start))?;
+        }
+
+        map.sections.push(LoadedSection {
+            name: program.name.clone(),
+            address,
+            length: program.length,
+            source: program.source.as_ref().map(|source| source.relocated(address.wrapping_sub(program.start))),
+            labels: program.labels.relocated(address.wrapping_sub(program.start)),
+        });
+        address = end;
+    }
+
+    // Pass 2 - copy text and apply modifications
+    let mut entry = None;
+    for (program, section) in programs.iter().zip(&map.sections) {
+        let delta = section.address.wrapping_sub(program.start);
+        let out_of_memory = |_| LoadError::OutOfMemory { section: program.name.clone() };
+
+        for record in &program.text {
+            for (ix, byte) in record.bytes.iter().enumerate() {
+                machine.write_byte(record.address.wrapping_add(delta).wrapping_add(ix as u32), *byte)
+                    .map_err(out_of_memory)?;
+            }
+        }
+
+        for modification in &program.modifications {
+            if !(1..=Modification::MAX_HALF_BYTES).contains(&modification.half_bytes) {
+                return Err(LoadError::InvalidModification {
+                    address: modification.address,
+                    half_bytes: modification.half_bytes,
+                    section: program.name.clone(),
+                });
+            }
+            let value = match &modification.symbol {
+                Some(symbol) => *map.symbols.get(symbol)
+                    .ok_or(LoadError::UndefinedSymbol { symbol: symbol.clone(), section: program.name.clone() })?,
+                None => delta,
+            };
+            modify(machine, modification, modification.address.wrapping_add(delta), value)
+                .map_err(out_of_memory)?;
+        }
+
+        if entry.is_none() {
+            entry = program.entry.map(|e| e.wrapping_add(delta));
+        }
+    }
+
+    map.entry = entry.unwrap_or(map.sections[0].address);
+    Ok(map)
+}
+
+fn define(map: &mut LoadMap, name: &str, address: u32) -> Result<(), LoadError> {
+    if name.is_empty() {
+        return Ok(());
+    }
+    if map.symbols.insert(name.to_string(), address).is_some() {
+        return Err(LoadError::DuplicateSymbol(name.to_string()));
+    }
+    Ok(())
+}
+
+/// Adds (or subtracts) the value to the field of `half_bytes` at the address.
+/// Fields with an odd length start in the low half of the first byte.
+fn modify(machine: &mut Machine, modification: &Modification, address: u32, value: u32) -> Result<(), MachineError> {
+    let bytes = (modification.half_bytes as u32).div_ceil(2);
+    let bits = modification.half_bytes as u32 * 4;
+    let mask = 1u32.checked_shl(bits).map_or(u32::MAX, |bit| bit - 1);
+
+    let mut field = 0u32;
+    for i in 0..bytes {
+        field = field.checked_shl(8).unwrap_or(0) | machine.read_byte(address + i)? as u32;
+    }
+
+    let modified = if modification.negative {
+        field.wrapping_sub(value)
+    } else {
+        field.wrapping_add(value)
+    };
+    let field = (field & !mask) | (modified & mask);
+
+    for i in 0..bytes {
+        machine.write_byte(address + i, field.checked_shr(8 * (bytes - 1 - i)).unwrap_or(0) as u8)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::ObjectError;
+    use crate::object::parser::parse_object;
+
+    fn load_object(machine: &mut Machine, object: &str, base: Option<u32>) -> Result<LoadMap, LoadError> {
+        let programs = parse_object(object).map_err(LoadError::Object)?;
+        load(machine, &programs, base)
+    }
+
+    #[test]
+    fn relocates_modified_fields() {
+        let object = "HPROG  00000000000C\nT0000000C4B1000090000000000000003\nM00000105\nM00000906\nE000000\n";
+        let mut machine = Machine::new();
+        let map = load_object(&mut machine, object, Some(0x1000)).unwrap();
+        assert_eq!(map.entry, 0x1000);
+        assert_eq!(machine.read_byte(0x1001).unwrap(), 0x10);
+        assert_eq!(machine.read_word(0x1001).unwrap() & 0xFFFFF, 0x1009);
+        assert_eq!(machine.read_word(0x1009).unwrap(), 0x1003);
+    }
+
+    #[test]
+    fn rejects_modification_longer_than_a_word() {
+        let object = "HPROG  000000000003\nT00000003000000\nM0000000A\nE000000\n";
+        let error = load_object(&mut Machine::new(), object, Some(0)).unwrap_err();
+        assert!(matches!(error, LoadError::Object(ObjectError { line: 3, .. })), "{error:?}");
+    }
+
+    #[test]
+    fn rejects_invalid_modification_of_program() {
+        let mut programs = parse_object("HPROG  000000000003\nT00000003000000\nE000000\n").unwrap();
+        programs[0].modifications.push(Modification::relocation(0, 8));
+        let error = load(&mut Machine::new(), &programs, None).unwrap_err();
+        assert_eq!(error, LoadError::InvalidModification { address: 0, half_bytes: 8, section: "PROG".to_string() });
+    }
+
+    #[test]
+    fn relocates_definitions() {
+        let object = "HPROG  001000000006\nDFIRST 001000LAST  001006\nE001000\n";
+        let map = load_object(&mut Machine::new(), object, Some(0)).unwrap();
+        assert_eq!(map.symbols["FIRST"], 0);
+        assert_eq!(map.symbols["LAST"], 6);
+    }
+
+    #[test]
+    fn rejects_definition_outside_of_section() {
+        let object = "HPROG  001000000006\nDBELOW 000FFF\nE001000\n";
+        let error = load_object(&mut Machine::new(), object, Some(0)).unwrap_err();
+        assert_eq!(error, LoadError::DefinitionOutOfRange {
+            symbol: "BELOW".to_string(),
+            address: 0xFFF,
+            section: "PROG".to_string(),
+        });
+
+        let object = "HPROG  001000000006\nDABOVE 001007\nE001000\n";
+        assert!(matches!(load_object(&mut Machine::new(), object, None), Err(LoadError::DefinitionOutOfRange { .. })));
+    }
+
+    #[test]
+    fn rejects_base_beyond_memory() {
+        let object = "HPROG  000000000003\nT00000003000000\nE000000\n";
+        for base in [0xFFFFFE, 0x1000000, u32::MAX] {
+            let error = load_object(&mut Machine::new(), object, Some(base)).unwrap_err();
+            assert_eq!(error, LoadError::OutOfMemory { section: "PROG".to_string() }, "{base:X}");
+        }
+        assert!(load_object(&mut Machine::new(), object, Some(0xFFFFFD)).is_ok());
+    }
+}
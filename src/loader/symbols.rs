@@ -0,0 +1,134 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use crate::errors::LoadError;
+
+///
+/// Names of addresses, to show and enter addresses symbolically.
+///
+/// Symbol files hold one `NAME ADDRESS` pair per line, with a hexadecimal address.
+/// Empty lines and lines starting with `.` are ignored.
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SymbolTable {
+    addresses: BTreeMap<String, u32>,
+    names: BTreeMap<u32, String>,
+}
+
+impl SymbolTable {
+    /// Adds the symbol. Names and addresses that are already known keep their first symbol.
+    pub fn insert(&mut self, name: &str, address: u32) {
+        if name.is_empty() {
+            return;
+        }
+        self.addresses.entry(name.to_string()).or_insert(address);
+        self.names.entry(address).or_insert_with(|| name.to_string());
+    }
+
+    /// Adds the symbols of the other table, keeping existing ones.
+    pub fn extend(&mut self, other: &SymbolTable) {
+        for (name, address) in &other.addresses {
+            self.insert(name, *address);
+        }
+    }
+
+    /// Looks up the address of the symbol. Exact names take precedence over differently cased ones.
+    pub fn address(&self, name: &str) -> Option<u32> {
+        self.addresses.get(name).copied().or_else(|| {
+            self.addresses
+                .iter()
+                .find(|(symbol, _)| symbol.eq_ignore_ascii_case(name))
+                .map(|(_, address)| *address)
+        })
+    }
+
+    /// Gets the symbol at exactly the address.
+    pub fn name(&self, address: u32) -> Option<&str> {
+        self.names.get(&address).map(String::as_str)
+    }
+
+    /// Finds the closest symbol at or before the address, along with the offset from it.
+    pub fn nearest(&self, address: u32) -> Option<(&str, u32)> {
+        self.names
+            .range(..=address)
+            .next_back()
+            .map(|(symbol_address, name)| (name.as_str(), address - symbol_address))
+    }
+
+    /// Formats the address as `name` or `name+offset` (hex), or as a plain hex address if no symbol precedes it.
+    pub fn describe(&self, address: u32) -> String {
+        match self.nearest(address) {
+            Some((name, 0)) => name.to_string(),
+            Some((name, offset)) => format!("{name}+{offset:X}"),
+            None => format!("{address:06X}"),
+        }
+    }
+
+    /// Parses `name`, `name+offset` or `name-offset` with a hex offset, or a hex address.
+    pub fn resolve(&self, text: &str) -> Option<u32> {
+        let text = text.trim();
+        if let Some(address) = self.address(text) {
+            return Some(address);
+        }
+        if let Some(index) = text.rfind(['+', '-']).filter(|index| *index > 0) {
+            let base = self.resolve(&text[..index])?;
+            let offset = u32::from_str_radix(text[index + 1..].trim(), 16).ok()?;
+            return Some(if &text[index..=index] == "+" { base.wrapping_add(offset) } else { base.wrapping_sub(offset) });
+        }
+        u32::from_str_radix(text, 16).ok()
+    }
+
+    /// Symbols sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u32)> {
+        self.addresses.iter().map(|(name, address)| (name.as_str(), *address))
+    }
+
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+
+    /// Moves all addresses by `delta`, wrapping around like the loader does.
+    pub fn relocated(&self, delta: u32) -> Self {
+        let mut table = Self::default();
+        for (name, address) in self.iter() {
+            table.insert(name, address.wrapping_add(delta));
+        }
+        table
+    }
+
+    /// Parses a symbol file.
+    pub fn parse(text: &str) -> Result<Self, LoadError> {
+        let mut table = Self::default();
+        for (index, line) in text.lines().enumerate() {
+            let line_no = index + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('.') {
+                continue;
+            }
+
+            let error = |message: &str| LoadError::SymbolFile { line: line_no, message: message.to_string() };
+            let mut fields = line.split_whitespace();
+            let (Some(name), Some(address), None) = (fields.next(), fields.next(), fields.next()) else {
+                return Err(error("Expected a name and an address."));
+            };
+            let address = u32::from_str_radix(address, 16)
+                .map_err(|_| error(&format!("Invalid address '{address}'.")))?;
+            table.insert(name, address);
+        }
+        Ok(table)
+    }
+}
+
+impl Display for SymbolTable {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut symbols = self.iter().collect::<Vec<_>>();
+        symbols.sort_by_key(|(name, address)| (*address, *name));
+        for (name, address) in symbols {
+            writeln!(f, "{name:<8} {address:06X}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use crate::errors::ConditionError;
+use crate::machine::machine::Machine;
+use crate::machine::register::Register;
+use crate::machine::word::{to_signed, WORD_MASK};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CompareOp {
+    // Longer operators first, so `<=` is not read as `<`
+    const ALL: [(&'static str, CompareOp); 6] = [
+        ("==", CompareOp::Eq),
+        ("!=", CompareOp::Ne),
+        ("<=", CompareOp::Le),
+        (">=", CompareOp::Ge),
+        ("<", CompareOp::Lt),
+        (">", CompareOp::Gt),
+    ];
+
+    fn symbol(self) -> &'static str {
+        Self::ALL.iter().find(|(_, op)| *op == self).map(|(symbol, _)| *symbol).unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Register(Register),
+    Value(u32),
+}
+
+impl Operand {
+    fn parse(text: &str, expression: &str) -> Result<Self, ConditionError> {
+        let text = text.trim();
+        if let Some(register) = Register::from_name(text) {
+            return Ok(Operand::Register(register));
+        }
+
+        let value = match text.strip_prefix("0x").or(text.strip_prefix("0X")) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok(),
+            None => text.parse::<i32>().ok().map(|value| value as u32),
+        };
+        value
+            .map(|value| Operand::Value(value & WORD_MASK))
+            .ok_or(ConditionError {
+                expression: expression.to_string(),
+                message: format!("Expected register or number, found '{text}'."),
+            })
+    }
+
+    fn value(&self, machine: &Machine) -> u32 {
+        match self {
+            Operand::Register(register) => machine.get_reg(register),
+            Operand::Value(value) => *value,
+        }
+    }
+}
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Operand::Register(register) => f.write_str(register.0),
+            Operand::Value(value) => write!(f, "0x{value:X}"),
+        }
+    }
+}
+
+///
+/// Condition on register values, e.g. `A == 5 && X >= 0x10 || T != S`.
+/// `&&` binds tighter than `||`, values are compared as signed words.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    // Disjunction of conjunctions
+    any: Vec<Vec<(Operand, CompareOp, Operand)>>,
+}
+
+impl Condition {
+    pub fn parse(expression: &str) -> Result<Self, ConditionError> {
+        let mut any = Vec::new();
+        for conjunction in expression.split("||") {
+            let mut all = Vec::new();
+            for comparison in conjunction.split("&&") {
+                let (left, op, right) = CompareOp::ALL
+                    .iter()
+                    .find_map(|(symbol, op)| {
+                        comparison.split_once(symbol).map(|(left, right)| (left, *op, right))
+                    })
+                    .ok_or(ConditionError {
+                        expression: expression.to_string(),
+                        message: format!("Expected comparison, found '{}'.", comparison.trim()),
+                    })?;
+                all.push((Operand::parse(left, expression)?, op, Operand::parse(right, expression)?));
+            }
+            any.push(all);
+        }
+
+        Ok(Self { any })
+    }
+
+    pub fn eval(&self, machine: &Machine) -> bool {
+        self.any.iter().any(|all| {
+            all.iter().all(|(left, op, right)| {
+                let left = to_signed(left.value(machine));
+                let right = to_signed(right.value(machine));
+                match op {
+                    CompareOp::Eq => left == right,
+                    CompareOp::Ne => left != right,
+                    CompareOp::Lt => left < right,
+                    CompareOp::Le => left <= right,
+                    CompareOp::Gt => left > right,
+                    CompareOp::Ge => left >= right,
+                }
+            })
+        })
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let any = self.any
+            .iter()
+            .map(|all| {
+                all.iter()
+                    .map(|(left, op, right)| format!("{left} {} {right}", op.symbol()))
+                    .collect::<Vec<_>>()
+                    .join(" && ")
+            })
+            .collect::<Vec<_>>();
+        f.write_str(&any.join(" || "))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Breakpoint {
+    pub address: u32,
+    pub enabled: bool,
+    /// Number of times execution stopped at this breakpoint.
+    pub hits: u64,
+    /// Execution only stops if the condition holds.
+    pub condition: Option<Condition>,
+}
+
+impl Breakpoint {
+    pub fn new(address: u32) -> Self {
+        Self { address, enabled: true, hits: 0, condition: None }
+    }
+
+    /// Checks whether execution should stop here in the current machine state.
+    pub fn triggers(&self, machine: &Machine) -> bool {
+        self.enabled && self.condition.as_ref().is_none_or(|condition| condition.eval(machine))
+    }
+}
+
+///
+/// Breakpoints by address.
+///
+#[derive(Debug, Default)]
+pub struct Breakpoints {
+    breakpoints: BTreeMap<u32, Breakpoint>,
+}
+
+impl Breakpoints {
+    /// Sets a breakpoint, replacing the one at the same address.
+    pub fn set(&mut self, breakpoint: Breakpoint) {
+        self.breakpoints.insert(breakpoint.address, breakpoint);
+    }
+
+    pub fn clear(&mut self, address: u32) -> Option<Breakpoint> {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn clear_all(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    /// Sets an unconditional breakpoint at the address, or clears the one that is there.
+    pub fn toggle(&mut self, address: u32) {
+        if self.clear(address).is_none() {
+            self.set(Breakpoint::new(address));
+        }
+    }
+
+    pub fn get(&self, address: u32) -> Option<&Breakpoint> {
+        self.breakpoints.get(&address)
+    }
+
+    pub fn get_mut(&mut self, address: u32) -> Option<&mut Breakpoint> {
+        self.breakpoints.get_mut(&address)
+    }
+
+    /// Lists breakpoints ordered by address.
+    pub fn list(&self) -> impl Iterator<Item = &Breakpoint> {
+        self.breakpoints.values()
+    }
+
+    /// Checks whether execution should stop at the address, counting the hit if it should.
+    pub(crate) fn hit(&mut self, address: u32, machine: &Machine) -> bool {
+        match self.breakpoints.get_mut(&address) {
+            Some(breakpoint) if breakpoint.triggers(machine) => {
+                breakpoint.hits += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+}
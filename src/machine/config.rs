@@ -0,0 +1,388 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::device::device_trait::Device;
+use crate::device::file_device::FileDevice;
+use crate::device::null_device::NullDevice;
+use crate::device::pipe_device::{Pipe, PipeDevice};
+use crate::device::split_device::SplitDevice;
+use crate::device::std_device::{StdErrDevice, StdInDevice, StdOutDevice};
+use crate::errors::ConfigError;
+
+///
+/// Where a device reads from and writes to.
+/// Relative paths are within the device directory.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum Backend {
+    /// File for reading and writing, created if missing, like the default `NN.dev` files.
+    File { path: PathBuf },
+    /// Existing file, read only.
+    Input { path: PathBuf },
+    /// File that is written only, truncated unless appending.
+    Output { path: PathBuf, append: bool },
+    /// Reads from one file and writes to another.
+    Files { input: PathBuf, output: PathBuf, append: bool },
+    Stdin,
+    Stdout,
+    Stderr,
+    /// Always ready, reads zeros and discards writes.
+    Null,
+    /// Devices with the same pipe name share a queue of bytes.
+    Pipe { name: String },
+    /// Connection to a local Unix socket.
+    Socket { path: PathBuf },
+    /// Private queue of bytes, starting with the data.
+    Memory { data: Vec<u8> },
+}
+
+///
+/// Machine configuration, read from a TOML file:
+///
+/// ```toml
+/// # Directory of the device files, the working directory by default
+/// directory = "devices"
+///
+/// [devices.05]
+/// type = "input"
+/// path = "numbers.txt"
+/// ```
+///
+/// Devices are numbered in hex. Only a subset of TOML is supported:
+/// tables, strings, integers and booleans.
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MachineConfig {
+    pub directory: Option<PathBuf>,
+    pub devices: BTreeMap<u8, Backend>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+}
+
+/// Values of a table, with the line they are set on
+type Keys = HashMap<String, (usize, Value)>;
+
+impl MachineConfig {
+    /// Configuration file read from the working directory, if it exists.
+    pub const FILE_NAME: &'static str = "machine.toml";
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Loads `machine.toml` from the working directory, or gives `None` if there is none.
+    pub fn load_default() -> Result<Option<Self>, ConfigError> {
+        if !Path::new(Self::FILE_NAME).exists() {
+            return Ok(None);
+        }
+        Self::load(Self::FILE_NAME).map(Some)
+    }
+
+    /// Directory of the device files.
+    pub fn device_directory(&self) -> &Path {
+        self.directory.as_deref().unwrap_or(Path::new("."))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, ConfigError> {
+        let mut config = Self::default();
+        // Device being defined, with the line of its table and its keys
+        let mut device: Option<(u8, usize, Keys)> = None;
+
+        for (index, line) in text.lines().enumerate() {
+            let line_no = index + 1;
+            let error = |message: String| ConfigError::Format { line: line_no, message };
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(table) = line.strip_prefix('[') {
+                let table = table.strip_suffix(']').ok_or_else(|| error("Missing ']'.".to_string()))?.trim();
+                if let Some((number, table_line, keys)) = device.take() {
+                    config.insert_device(number, table_line, keys)?;
+                }
+                let number = table
+                    .strip_prefix("devices.")
+                    .ok_or_else(|| error(format!("Unknown table '{table}', expected [devices.NN].")))?;
+                let number = number.trim().trim_matches('"');
+                let number = u8::from_str_radix(number, 16)
+                    .map_err(|_| error(format!("Invalid device number '{number}', expected hex.")))?;
+                if config.devices.contains_key(&number) {
+                    return Err(error(format!("Device {number:02X} is defined twice.")));
+                }
+                device = Some((number, line_no, HashMap::new()));
+                continue;
+            }
+
+            let (key, value) = line.split_once('=').ok_or_else(|| error("Expected 'key = value'.".to_string()))?;
+            let key = key.trim().trim_matches('"').to_string();
+            let value = parse_value(value.trim()).map_err(error)?;
+            match &mut device {
+                Some((_, _, keys)) => {
+                    if keys.insert(key.clone(), (line_no, value)).is_some() {
+                        return Err(error(format!("Key '{key}' is set twice.")));
+                    }
+                }
+                None if key == "directory" => match value {
+                    Value::String(directory) => config.directory = Some(PathBuf::from(directory)),
+                    _ => return Err(error("The directory must be a string.".to_string())),
+                },
+                None => return Err(error(format!("Unknown key '{key}'."))),
+            }
+        }
+
+        if let Some((number, table_line, keys)) = device {
+            config.insert_device(number, table_line, keys)?;
+        }
+        Ok(config)
+    }
+
+    fn insert_device(&mut self, number: u8, table_line: usize, mut keys: Keys) -> Result<(), ConfigError> {
+        let mut take_string = |key: &str| match keys.remove(key) {
+            Some((_, Value::String(value))) => Ok(Some(value)),
+            Some((line, _)) => Err(ConfigError::Format { line, message: format!("'{key}' must be a string.") }),
+            None => Ok(None),
+        };
+        let kind = take_string("type")?.ok_or(ConfigError::Format {
+            line: table_line,
+            message: format!("Device {number:02X} has no type."),
+        })?;
+        let required = |value: Option<String>, key: &str| {
+            value.ok_or(ConfigError::Format {
+                line: table_line,
+                message: format!("Device {number:02X} of type '{kind}' needs '{key}'."),
+            })
+        };
+
+        let backend = match kind.as_str() {
+            "file" => Backend::File { path: required(take_string("path")?, "path")?.into() },
+            "input" => Backend::Input { path: required(take_string("path")?, "path")?.into() },
+            "output" => Backend::Output { path: required(take_string("path")?, "path")?.into(), append: false },
+            "files" => Backend::Files {
+                input: required(take_string("input")?, "input")?.into(),
+                output: required(take_string("output")?, "output")?.into(),
+                append: false,
+            },
+            "stdin" => Backend::Stdin,
+            "stdout" => Backend::Stdout,
+            "stderr" => Backend::Stderr,
+            "null" => Backend::Null,
+            "pipe" => Backend::Pipe { name: required(take_string("name")?, "name")? },
+            "socket" => Backend::Socket { path: required(take_string("path")?, "path")?.into() },
+            "memory" => Backend::Memory { data: take_string("data")?.unwrap_or_default().into_bytes() },
+            _ => {
+                return Err(ConfigError::Format {
+                    line: table_line,
+                    message: format!("Unknown device type '{kind}'."),
+                })
+            }
+        };
+
+        let backend = match (backend, keys.remove("append")) {
+            (Backend::Output { path, .. }, Some((_, Value::Boolean(append)))) => Backend::Output { path, append },
+            (Backend::Files { input, output, .. }, Some((_, Value::Boolean(append)))) => Backend::Files { input, output, append },
+            (_, Some((line, _))) => {
+                return Err(ConfigError::Format { line, message: "'append' must be a boolean for output files.".to_string() })
+            }
+            (backend, None) => backend,
+        };
+        if let Some((key, (line, _))) = keys.into_iter().min_by_key(|(_, (line, _))| *line) {
+            return Err(ConfigError::Format { line, message: format!("Unknown key '{key}' for type '{kind}'.") });
+        }
+
+        self.devices.insert(number, backend);
+        Ok(())
+    }
+
+    /// Opens the devices. Devices on the same pipe share it.
+    pub fn open_devices(&self) -> Result<BTreeMap<u8, Box<dyn Device>>, ConfigError> {
+        let directory = self.device_directory();
+        let mut pipes = HashMap::<&str, Pipe>::new();
+        let mut devices = BTreeMap::new();
+        for (&number, backend) in &self.devices {
+            let error = |e: std::io::Error| ConfigError::Device { device: number, message: e.to_string() };
+            let device: Box<dyn Device> = match backend {
+                Backend::File { path } => Box::new(FileDevice::open(&directory.join(path)).map_err(error)?),
+                Backend::Input { path } => Box::new(FileDevice::input(&directory.join(path)).map_err(error)?),
+                Backend::Output { path, append } => {
+                    Box::new(FileDevice::output(&directory.join(path), *append).map_err(error)?)
+                }
+                Backend::Files { input, output, append } => Box::new(SplitDevice::new(
+                    Box::new(FileDevice::input(&directory.join(input)).map_err(error)?),
+                    Box::new(FileDevice::output(&directory.join(output), *append).map_err(error)?),
+                )),
+                Backend::Stdin => Box::new(StdInDevice),
+                Backend::Stdout => Box::new(StdOutDevice),
+                Backend::Stderr => Box::new(StdErrDevice),
+                Backend::Null => Box::new(NullDevice),
+                Backend::Pipe { name } => Box::new(PipeDevice::new(pipes.entry(name).or_default().clone())),
+                Backend::Socket { path } => socket(&directory.join(path)).map_err(error)?,
+                Backend::Memory { data } => Box::new(PipeDevice::new(Pipe::with_data(data))),
+            };
+            devices.insert(number, device);
+        }
+        Ok(devices)
+    }
+}
+
+#[cfg(unix)]
+fn socket(path: &Path) -> std::io::Result<Box<dyn Device>> {
+    Ok(Box::new(crate::device::socket_device::SocketDevice::connect(path)?))
+}
+
+#[cfg(not(unix))]
+fn socket(_path: &Path) -> std::io::Result<Box<dyn Device>> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "Unix sockets are not supported on this platform"))
+}
+
+/// Removes a `#` comment that is not inside a string.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_value(value: &str) -> Result<Value, String> {
+    if let Some(string) = value.strip_prefix('"') {
+        let string = string.strip_suffix('"').ok_or(format!("Unterminated string {value}."))?;
+        return unescape(string).map(Value::String);
+    }
+    match value {
+        "true" => return Ok(Value::Boolean(true)),
+        "false" => return Ok(Value::Boolean(false)),
+        _ => {}
+    }
+    let digits = value.replace('_', "");
+    let integer = match digits.strip_prefix("0x") {
+        Some(hex) => i64::from_str_radix(hex, 16),
+        None => digits.parse(),
+    };
+    integer.map(Value::Integer).map_err(|_| format!("Invalid value '{value}'."))
+}
+
+/// Resolves the escapes of a basic string: `\n`, `\t`, `\r`, `\"`, `\\` and `\uXXXX`.
+fn unescape(string: &str) -> Result<String, String> {
+    let mut result = String::new();
+    let mut chars = string.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some('r') => result.push('\r'),
+            Some('"') => result.push('"'),
+            Some('\\') => result.push('\\'),
+            Some('u') => {
+                let code = chars.by_ref().take(4).collect::<String>();
+                let c = u32::from_str_radix(&code, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or(format!("Invalid escape '\\u{code}'."))?;
+                result.push(c);
+            }
+            Some(c) => return Err(format!("Invalid escape '\\{c}'.")),
+            None => return Err("String ends with '\\'.".to_string()),
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format_error(text: &str) -> (usize, String) {
+        match MachineConfig::parse(text) {
+            Err(ConfigError::Format { line, message }) => (line, message),
+            other => panic!("expected a format error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn parses_devices() {
+        let config = MachineConfig::parse(
+            r#"
+# Device files
+directory = "devices"   # relative to the working directory
+
+[devices.05]
+type = "input"
+path = "numbers.txt"
+
+[devices.F1]
+type = "output"
+path = "out#1.txt"
+append = true
+
+[devices."0A"]
+type = "memory"
+data = "a\tb\nA"
+
+[devices.0B]
+type = "pipe"
+name = "link"
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.device_directory(), Path::new("devices"));
+        assert_eq!(config.devices.len(), 4);
+        assert_eq!(config.devices[&0x05], Backend::Input { path: "numbers.txt".into() });
+        assert_eq!(config.devices[&0xF1], Backend::Output { path: "out#1.txt".into(), append: true });
+        assert_eq!(config.devices[&0x0A], Backend::Memory { data: b"a\tb\nA".to_vec() });
+        assert_eq!(config.devices[&0x0B], Backend::Pipe { name: "link".to_string() });
+    }
+
+    #[test]
+    fn defaults_to_the_working_directory() {
+        let config = MachineConfig::parse("[devices.01]\ntype = \"stdout\"\n").unwrap();
+        assert_eq!(config.device_directory(), Path::new("."));
+        assert_eq!(config.devices[&1], Backend::Stdout);
+    }
+
+    #[test]
+    fn reports_errors_with_their_line() {
+        assert_eq!(format_error("[devices.05]\ntype = \"input\"\n").0, 1);
+        assert_eq!(format_error("[devices.05]\ntype = \"null\"\n[devices.05]\ntype = \"null\"\n").0, 3);
+        assert_eq!(format_error("[devices.100]\n").0, 1);
+        assert_eq!(format_error("[machine]\n").0, 1);
+        assert_eq!(format_error("[devices.01]\ntype = \"null\"\ncolor = \"red\"\n").0, 3);
+        assert_eq!(format_error("[devices.01]\ntype = \"file\"\npath = \"a\"\nappend = 1\n").0, 4);
+        assert_eq!(format_error("[devices.01]\ntype = \"file\"\npath = \"a\"\nappend = true\n").0, 4);
+        assert_eq!(format_error("\n\ndirectory = 5\n").0, 3);
+        assert_eq!(format_error("[devices.01]\ntype = \"tape\"\n").1, "Unknown device type 'tape'.");
+        assert_eq!(format_error("[devices.01]\ntype = \"pipe\nname = \"x\"\n").0, 2);
+        assert_eq!(format_error("[devices.01]\ntype = \"a\\qb\"\n").1, "Invalid escape '\\q'.");
+    }
+
+    #[test]
+    fn opens_memory_and_pipe_devices() {
+        let config = MachineConfig::parse(
+            "[devices.01]\ntype = \"pipe\"\nname = \"p\"\n[devices.02]\ntype = \"pipe\"\nname = \"p\"\n[devices.03]\ntype = \"memory\"\ndata = \"hi\"\n",
+        )
+        .unwrap();
+        let mut devices = config.open_devices().unwrap();
+
+        devices.get_mut(&1).unwrap().write(b'x').unwrap();
+        assert!(devices[&2].test());
+        assert_eq!(devices.get_mut(&2).unwrap().read().unwrap(), b'x');
+        assert_eq!(devices.get_mut(&3).unwrap().read().unwrap(), b'h');
+    }
+}
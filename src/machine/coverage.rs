@@ -0,0 +1,203 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use bitflags::bitflags;
+use crate::asm::source_map::{SourceLine, SourceMap};
+use crate::loader::loader::{LoadMap, LoadedSection};
+use crate::machine::machine::Machine;
+use crate::machine::watchpoint::Access;
+use crate::opcode::decoder::decode;
+use crate::opcode::opcode::Opcode;
+
+bitflags! {
+    ///
+    /// How a byte was used while running.
+    ///
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Usage: u8 {
+        /// Start of an executed instruction
+        const EXECUTED = 1 << 0;
+        const READ = 1 << 1;
+        const WRITTEN = 1 << 2;
+    }
+}
+
+///
+/// Outcomes of a conditional jump.
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Branch {
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+///
+/// Executed instructions, taken branches and data accesses of the loaded programs.
+///
+#[derive(Debug, Clone, Default)]
+pub struct Coverage {
+    loaded: BTreeSet<u32>,
+    usage: BTreeMap<u32, Usage>,
+    executions: BTreeMap<u32, u64>,
+    branches: BTreeMap<u32, Branch>,
+}
+
+impl Coverage {
+    pub(crate) fn set_loaded(&mut self, addresses: impl IntoIterator<Item = u32>) {
+        self.loaded = addresses.into_iter().collect();
+    }
+
+    /// Records an instruction start. `taken` is set for conditional jumps.
+    pub(crate) fn record_execution(&mut self, address: u32, taken: Option<bool>) {
+        self.add_usage(address, Usage::EXECUTED);
+        *self.executions.entry(address).or_default() += 1;
+        if let Some(taken) = taken {
+            let branch = self.branches.entry(address).or_default();
+            if taken {
+                branch.taken += 1;
+            } else {
+                branch.not_taken += 1;
+            }
+        }
+    }
+
+    pub(crate) fn record_access(&mut self, address: u32, access: Access) {
+        if access.contains(Access::READ) {
+            self.add_usage(address, Usage::READ);
+        }
+        if access.contains(Access::WRITE) {
+            self.add_usage(address, Usage::WRITTEN);
+        }
+    }
+
+    fn add_usage(&mut self, address: u32, usage: Usage) {
+        *self.usage.entry(address).or_insert(Usage::empty()) |= usage;
+    }
+
+    pub fn clear(&mut self) {
+        self.usage.clear();
+        self.executions.clear();
+        self.branches.clear();
+    }
+
+    /// Addresses of the bytes loaded from T records.
+    pub fn loaded(&self) -> &BTreeSet<u32> {
+        &self.loaded
+    }
+
+    pub fn usage(&self, address: u32) -> Usage {
+        self.usage.get(&address).copied().unwrap_or(Usage::empty())
+    }
+
+    /// Number of times the instruction at the address was executed.
+    pub fn executions(&self, address: u32) -> u64 {
+        self.executions.get(&address).copied().unwrap_or_default()
+    }
+
+    pub fn branch(&self, address: u32) -> Option<Branch> {
+        self.branches.get(&address).copied()
+    }
+
+    /// Combined usage of the bytes of a statement.
+    fn line_usage(&self, line: &SourceLine) -> Usage {
+        (line.address..line.address + line.length).fold(Usage::empty(), |usage, address| usage | self.usage(address))
+    }
+
+    /// Exports the coverage of assembled sections in the lcov tracefile format.
+    /// Instructions are reported as lines and conditional jumps as branches.
+    pub fn lcov(&self, map: &LoadMap, machine: &Machine) -> String {
+        let mut out = String::new();
+        for section in &map.sections {
+            let Some(source) = &section.source else {
+                continue;
+            };
+            let file = source.path.as_ref().map_or(section.name.clone(), |path| path.display().to_string());
+            let instructions = source.lines.iter().filter(|line| line.instruction).collect::<Vec<_>>();
+
+            let _ = writeln!(out, "TN:{}", section.name);
+            let _ = writeln!(out, "SF:{file}");
+            let (mut found, mut hit) = (0, 0);
+            for line in instructions.iter().filter(|line| conditional_jump(machine, line.address)) {
+                let branch = self.branch(line.address).unwrap_or_default();
+                let count = |count: u64| {
+                    if self.executions(line.address) == 0 { "-".to_string() } else { count.to_string() }
+                };
+                let _ = writeln!(out, "BRDA:{},0,0,{}", line.line, count(branch.taken));
+                let _ = writeln!(out, "BRDA:{},0,1,{}", line.line, count(branch.not_taken));
+                found += 2;
+                hit += (branch.taken > 0) as u32 + (branch.not_taken > 0) as u32;
+            }
+            let _ = writeln!(out, "BRF:{found}");
+            let _ = writeln!(out, "BRH:{hit}");
+
+            for line in &instructions {
+                let _ = writeln!(out, "DA:{},{}", line.line, self.executions(line.address));
+            }
+            let _ = writeln!(out, "LF:{}", instructions.len());
+            let _ = writeln!(out, "LH:{}", instructions.iter().filter(|line| self.executions(line.address) > 0).count());
+            let _ = writeln!(out, "end_of_record");
+        }
+        out
+    }
+
+    /// Annotates the source of each assembled section with execution counts,
+    /// `#####` for instructions never executed and `r`/`w` for data that was read or written.
+    pub fn listing(&self, map: &LoadMap) -> String {
+        let mut out = String::new();
+        for section in &map.sections {
+            match &section.source {
+                Some(source) => self.section_listing(&mut out, section, source),
+                None => {
+                    let bytes = self.loaded.range(section.address..section.address + section.length);
+                    let (mut executed, mut data, mut loaded) = (0, 0, 0);
+                    for address in bytes {
+                        let usage = self.usage(*address);
+                        executed += usage.contains(Usage::EXECUTED) as u32;
+                        data += usage.intersects(Usage::READ | Usage::WRITTEN) as u32;
+                        loaded += 1;
+                    }
+                    let _ = writeln!(out, "{}: no source, {executed} instructions executed, \
+                                           {data} of {loaded} loaded bytes used as data\n", section.name);
+                }
+            }
+        }
+        out
+    }
+
+    fn section_listing(&self, out: &mut String, section: &LoadedSection, source: &SourceMap) {
+        let instructions = source.lines.iter().filter(|line| line.instruction).collect::<Vec<_>>();
+        let executed = instructions.iter().filter(|line| self.executions(line.address) > 0).count();
+        let file = source.path.as_ref().map(|path| format!(" ({})", path.display())).unwrap_or_default();
+        let _ = writeln!(out, "{}{file}: {executed} of {} instructions executed", section.name, instructions.len());
+
+        for (index, text) in source.text.iter().enumerate() {
+            let number = index + 1;
+            let (count, data, address) = match source.line(number) {
+                Some(line) if line.instruction => {
+                    let count = match self.executions(line.address) {
+                        0 => "#####".to_string(),
+                        count => count.to_string(),
+                    };
+                    (count, String::new(), format!("{:06X}", line.address))
+                }
+                Some(line) => {
+                    let usage = self.line_usage(line);
+                    let read = if usage.contains(Usage::READ) { "r" } else { "" };
+                    let written = if usage.contains(Usage::WRITTEN) { "w" } else { "" };
+                    (String::new(), format!("{read}{written}"), format!("{:06X}", line.address))
+                }
+                None => (String::new(), String::new(), String::new()),
+            };
+            let branch = source
+                .line(number)
+                .and_then(|line| self.branch(line.address))
+                .map(|branch| format!("  [taken {}, not taken {}]", branch.taken, branch.not_taken))
+                .unwrap_or_default();
+            let _ = writeln!(out, "{count:>8} {data:<2} {address:<6} {number:>5}  {text}{branch}");
+        }
+        out.push('\n');
+    }
+}
+
+fn conditional_jump(machine: &Machine, address: u32) -> bool {
+    decode(machine, address).is_ok_and(|decoded| matches!(decoded.opcode(), Opcode::JEQ | Opcode::JGT | Opcode::JLT))
+}
@@ -0,0 +1,224 @@
+use std::fmt::{Display, Formatter};
+
+///
+/// 48-bit SIC/XE floating point number: 1 sign bit, 11-bit exponent and 36-bit fraction.
+/// The value is `fraction * 2^(exponent - 1024)`, where a normalized fraction is in [0.5, 1).
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SicFloat(u64);
+
+impl SicFloat {
+    pub const BYTES: usize = 6;
+    const MASK: u64 = 0xFFFF_FFFF_FFFF;
+    const FRACTION_BITS: u32 = 36;
+    const FRACTION_MASK: u64 = (1 << Self::FRACTION_BITS) - 1;
+    const EXPONENT_MASK: u64 = 0x7FF;
+    const EXPONENT_BIAS: i64 = 1024;
+    const SIGN: u64 = 1 << 47;
+
+    pub fn from_bits(bits: u64) -> Self {
+        Self(bits & Self::MASK)
+    }
+
+    pub fn to_bits(self) -> u64 {
+        self.0
+    }
+
+    pub fn from_bytes(bytes: [u8; Self::BYTES]) -> Self {
+        Self(bytes.iter().fold(0, |bits, byte| bits << 8 | *byte as u64))
+    }
+
+    pub fn to_bytes(self) -> [u8; Self::BYTES] {
+        let mut bytes = [0; Self::BYTES];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = (self.0 >> (8 * (Self::BYTES - 1 - i))) as u8;
+        }
+        bytes
+    }
+
+    fn negative(self) -> bool {
+        self.0 & Self::SIGN != 0
+    }
+
+    fn exponent(self) -> i64 {
+        ((self.0 >> Self::FRACTION_BITS) & Self::EXPONENT_MASK) as i64
+    }
+
+    fn fraction(self) -> u64 {
+        self.0 & Self::FRACTION_MASK
+    }
+
+    fn compose(negative: bool, exponent: i64, fraction: u64) -> Self {
+        let sign = if negative { Self::SIGN } else { 0 };
+        Self(sign | (exponent as u64 & Self::EXPONENT_MASK) << Self::FRACTION_BITS | fraction & Self::FRACTION_MASK)
+    }
+
+    pub fn to_f64(self) -> f64 {
+        let fraction = self.fraction() as f64 / (1u64 << Self::FRACTION_BITS) as f64;
+        let value = fraction * 2f64.powi((self.exponent() - Self::EXPONENT_BIAS) as i32);
+        if self.negative() { -value } else { value }
+    }
+
+    /// Converts the value, rounding the fraction to 36 bits.
+    /// Values too large saturate, values too small become zero.
+    pub fn from_f64(value: f64) -> Self {
+        if value == 0.0 || value.is_nan() || value.is_subnormal() {
+            return Self(0);
+        }
+
+        let negative = value.is_sign_negative();
+        if value.is_infinite() {
+            return Self::compose(negative, Self::EXPONENT_MASK as i64, Self::FRACTION_MASK);
+        }
+
+        // f64 is 1.m * 2^(e - 1023), which is 0.1m * 2^(e - 1022)
+        let bits = value.to_bits();
+        let mut exponent = ((bits >> 52) & 0x7FF) as i64 - 1022;
+        let mantissa = (bits & ((1 << 52) - 1)) | 1 << 52;
+
+        // Round 53 bits of mantissa to 36 bits of fraction
+        let shift = 53 - Self::FRACTION_BITS;
+        let mut fraction = (mantissa + (1 << (shift - 1))) >> shift;
+        if fraction >> Self::FRACTION_BITS != 0 {
+            fraction >>= 1;
+            exponent += 1;
+        }
+
+        let exponent = exponent + Self::EXPONENT_BIAS;
+        if exponent < 0 {
+            Self(0)
+        } else if exponent > Self::EXPONENT_MASK as i64 {
+            Self::compose(negative, Self::EXPONENT_MASK as i64, Self::FRACTION_MASK)
+        } else {
+            Self::compose(negative, exponent, fraction)
+        }
+    }
+
+    /// Shifts the fraction left until its highest bit is set, adjusting the exponent.
+    pub fn normalize(self) -> Self {
+        let mut fraction = self.fraction();
+        let mut exponent = self.exponent();
+        if fraction == 0 {
+            return Self(0);
+        }
+
+        while fraction & (1 << (Self::FRACTION_BITS - 1)) == 0 && exponent > 0 {
+            fraction <<= 1;
+            exponent -= 1;
+        }
+
+        Self::compose(self.negative(), exponent, fraction)
+    }
+}
+
+impl Display for SicFloat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_f64())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assembler::assemble;
+    use crate::machine::register::Register;
+    use crate::machine::runner::Runner;
+    use crate::machine::word::from_signed;
+
+    /// Runs the assembled source until it halts.
+    fn run(source: &str) -> Runner {
+        let mut runner = Runner::new();
+        runner.load_file(assemble(source).unwrap().to_string().as_bytes()).unwrap();
+        while runner.halted().is_none() {
+            runner.step().unwrap();
+        }
+        runner
+    }
+
+    #[test]
+    fn converts_exact_values() {
+        for (value, bits) in [
+            (1.0, 0x4018_0000_0000),
+            (-1.0, 0xC018_0000_0000),
+            (0.5, 0x4008_0000_0000),
+            (3.0, 0x402C_0000_0000),
+            (-0.75, 0xC00C_0000_0000),
+        ] {
+            let float = SicFloat::from_f64(value);
+            assert_eq!(float.to_bits(), bits, "{value}");
+            assert_eq!(float.to_f64(), value);
+        }
+    }
+
+    #[test]
+    fn zero_has_no_sign_or_exponent() {
+        assert_eq!(SicFloat::from_f64(0.0).to_bits(), 0);
+        assert_eq!(SicFloat::from_f64(-0.0).to_bits(), 0);
+        assert_eq!(SicFloat::from_f64(f64::NAN).to_bits(), 0);
+        assert_eq!(SicFloat::default().to_f64(), 0.0);
+    }
+
+    #[test]
+    fn rounds_fraction_to_36_bits() {
+        // One ulp of 1.0 is 2^-35; half an ulp rounds up, less rounds down
+        assert_eq!(SicFloat::from_f64(1.0 + 2f64.powi(-35)).to_f64(), 1.0 + 2f64.powi(-35));
+        assert_eq!(SicFloat::from_f64(1.0 + 2f64.powi(-36)).to_f64(), 1.0 + 2f64.powi(-35));
+        assert_eq!(SicFloat::from_f64(1.0 + 2f64.powi(-37)).to_f64(), 1.0);
+        // Rounding up a fraction of all ones carries into the exponent
+        assert_eq!(SicFloat::from_f64(1.0 - 2f64.powi(-40)).to_bits(), 0x4018_0000_0000);
+    }
+
+    #[test]
+    fn saturates_large_values_and_flushes_small_ones() {
+        assert_eq!(SicFloat::from_f64(f64::MAX).to_bits(), 0x7FFF_FFFF_FFFF);
+        assert_eq!(SicFloat::from_f64(f64::INFINITY).to_bits(), 0x7FFF_FFFF_FFFF);
+        assert_eq!(SicFloat::from_f64(f64::NEG_INFINITY).to_bits(), 0xFFFF_FFFF_FFFF);
+        assert_eq!(SicFloat::from_f64(f64::MIN_POSITIVE / 4.0).to_bits(), 0);
+        // The smallest normal f64 still fits, with the exponent near 0
+        assert_eq!(SicFloat::from_f64(f64::MIN_POSITIVE).to_f64(), f64::MIN_POSITIVE);
+    }
+
+    #[test]
+    fn normalizes_fraction() {
+        // 0.0625 * 2^0 is 0.5 * 2^-3
+        let float = SicFloat::compose(true, 0x400, 1 << 32);
+        let normalized = float.normalize();
+        assert_eq!(normalized.to_bits(), 0xBFD8_0000_0000);
+        assert_eq!(normalized.to_f64(), float.to_f64());
+        assert_eq!(SicFloat::compose(true, 0x400, 0).normalize().to_bits(), 0);
+        // The exponent stops at 0
+        assert_eq!(SicFloat::compose(false, 1, 1).normalize().to_bits(), 2);
+    }
+
+    #[test]
+    fn bytes_are_big_endian() {
+        let float = SicFloat::from_f64(-3.0);
+        assert_eq!(float.to_bytes(), [0xC0, 0x2C, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(SicFloat::from_bytes(float.to_bytes()), float);
+    }
+
+    #[test]
+    fn loads_and_stores_six_bytes() {
+        let runner = run("P      START 0\n       LDF   ONE\n       STF   COPY\nhalt   J     halt\nONE    BYTE  X'401800000000'\nCOPY   RESB  6\nAFTER  BYTE  X'AA'\n       END\n");
+        let machine = runner.machine();
+        assert_eq!(machine.get_f_reg().to_f64(), 1.0);
+        let copy = (0..6).map(|i| machine.read_byte(0x0F + i).unwrap()).collect::<Vec<_>>();
+        assert_eq!(copy, [0x40, 0x18, 0, 0, 0, 0]);
+        assert_eq!(machine.read_byte(0x15).unwrap(), 0xAA);
+    }
+
+    #[test]
+    fn fix_truncates_towards_zero() {
+        for (value, fixed) in [(2.75, 2), (-2.75, -2), (0.5, 0), (-0.5, 0)] {
+            let bytes = SicFloat::from_f64(value).to_bytes().iter().map(|b| format!("{b:02X}")).collect::<String>();
+            let runner = run(&format!("P      START 0\n       LDF   VALUE\n       FIX\nhalt   J     halt\nVALUE  BYTE  X'{bytes}'\n       END\n"));
+            assert_eq!(runner.machine().get_reg(&Register::A), from_signed(fixed), "{value}");
+        }
+    }
+
+    #[test]
+    fn float_converts_signed_words() {
+        let runner = run("P      START 0\n       LDA   #0\n       SUB   #3\n       FLOAT\nhalt   J     halt\n       END\n");
+        assert_eq!(runner.machine().get_f_reg().to_f64(), -3.0);
+    }
+}
@@ -0,0 +1,234 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use crate::machine::machine::Machine;
+
+///
+/// How pixels are stored in the framebuffer.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PixelFormat {
+    /// One byte per pixel, an index into the 256-colour xterm palette.
+    Palette8,
+    /// Four pixels per byte, leftmost pixel in the highest bits:
+    /// black, cyan, magenta and white.
+    Color2,
+}
+
+impl PixelFormat {
+    pub fn bits(&self) -> u32 {
+        match self {
+            PixelFormat::Palette8 => 8,
+            PixelFormat::Color2 => 2,
+        }
+    }
+
+    /// Parses the number of bits per pixel, `8` or `2`.
+    pub fn from_bits(bits: u32) -> Option<Self> {
+        match bits {
+            8 => Some(PixelFormat::Palette8),
+            2 => Some(PixelFormat::Color2),
+            _ => None,
+        }
+    }
+
+    /// Gets the colour of the pixel value as RGB.
+    pub fn color(&self, value: u8) -> [u8; 3] {
+        match self {
+            PixelFormat::Palette8 => xterm_color(value),
+            PixelFormat::Color2 => [[0, 0, 0], [0x55, 0xFF, 0xFF], [0xFF, 0x55, 0xFF], [0xFF, 0xFF, 0xFF]][value as usize & 3],
+        }
+    }
+}
+
+///
+/// Pixels mapped to memory, row after row starting at the top left corner.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Framebuffer {
+    pub base: u32,
+    pub width: u32,
+    pub height: u32,
+    pub format: PixelFormat,
+}
+
+impl Default for Framebuffer {
+    /// 64x48 pixels with the 8-bit palette at 0xA000, below the text screen.
+    fn default() -> Self {
+        Self { base: Self::DEFAULT_BASE, width: 64, height: 48, format: PixelFormat::Palette8 }
+    }
+}
+
+impl Framebuffer {
+    pub const DEFAULT_BASE: u32 = 0xA000;
+
+    /// Number of bytes the framebuffer occupies, saturating at `u32::MAX`.
+    pub fn size(&self) -> u32 {
+        let bits = self.width as u64 * self.height as u64 * self.format.bits() as u64;
+        u32::try_from(bits.div_ceil(8)).unwrap_or(u32::MAX)
+    }
+
+    /// Checks whether the whole framebuffer is within memory.
+    pub fn fits(&self) -> bool {
+        self.base.checked_add(self.size()).is_some_and(|end| end <= Machine::MAX_ADDRESS + 1)
+    }
+
+    pub fn contains(&self, address: u32) -> bool {
+        address.checked_sub(self.base).is_some_and(|offset| offset < self.size())
+    }
+
+    /// Gets the value of the pixel, starting at 0. Pixels outside of memory are 0.
+    pub fn pixel(&self, machine: &Machine, x: u32, y: u32) -> u8 {
+        let bits = self.format.bits() as u64;
+        let index = y as u64 * self.width as u64 + x as u64;
+        let address = u32::try_from(index * bits / 8).ok().and_then(|offset| self.base.checked_add(offset));
+        let byte = address.and_then(|address| machine.peek_byte(address).ok()).unwrap_or_default();
+        let shift = 8 - bits - index * bits % 8;
+        (byte >> shift) & ((1u16 << bits) - 1) as u8
+    }
+
+    /// Reads the pixels from memory.
+    pub fn frame(&self, machine: &Machine) -> Frame {
+        let pixels = (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+            .map(|(x, y)| self.format.color(self.pixel(machine, x, y)))
+            .collect();
+        Frame { width: self.width, height: self.height, pixels }
+    }
+}
+
+/// Colour of the 256-colour xterm palette: 16 system colours, a 6x6x6 cube and 24 greys.
+fn xterm_color(index: u8) -> [u8; 3] {
+    const SYSTEM: [[u8; 3]; 16] = [
+        [0x00, 0x00, 0x00], [0x80, 0x00, 0x00], [0x00, 0x80, 0x00], [0x80, 0x80, 0x00],
+        [0x00, 0x00, 0x80], [0x80, 0x00, 0x80], [0x00, 0x80, 0x80], [0xC0, 0xC0, 0xC0],
+        [0x80, 0x80, 0x80], [0xFF, 0x00, 0x00], [0x00, 0xFF, 0x00], [0xFF, 0xFF, 0x00],
+        [0x00, 0x00, 0xFF], [0xFF, 0x00, 0xFF], [0x00, 0xFF, 0xFF], [0xFF, 0xFF, 0xFF],
+    ];
+    match index {
+        0..=15 => SYSTEM[index as usize],
+        16..=231 => {
+            let level = |value: u8| if value == 0 { 0 } else { 55 + value * 40 };
+            let index = index - 16;
+            [level(index / 36), level(index / 6 % 6), level(index % 6)]
+        }
+        _ => {
+            let grey = 8 + (index - 232) * 10;
+            [grey; 3]
+        }
+    }
+}
+
+///
+/// RGB image of the framebuffer.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub width: u32,
+    pub height: u32,
+    /// Pixels row after row
+    pub pixels: Vec<[u8; 3]>,
+}
+
+impl Frame {
+    /// Encodes the frame as a binary PPM (P6) image.
+    pub fn to_ppm(&self) -> Vec<u8> {
+        let mut ppm = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        ppm.extend(self.pixels.iter().flatten());
+        ppm
+    }
+
+    /// Encodes the frame as an RGB PNG image, with uncompressed image data.
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut raw = Vec::with_capacity(self.pixels.len() * 3 + self.height as usize);
+        for row in self.pixels.chunks(self.width.max(1) as usize) {
+            raw.push(0); // No filter
+            raw.extend(row.iter().flatten());
+        }
+
+        // zlib stream of stored deflate blocks
+        let mut data = vec![0x78, 0x01];
+        let mut blocks = raw.chunks(0xFFFF).peekable();
+        if blocks.peek().is_none() {
+            data.extend([1, 0, 0, 0xFF, 0xFF]);
+        }
+        while let Some(block) = blocks.next() {
+            let length = block.len() as u16;
+            data.push(blocks.peek().is_none() as u8);
+            data.extend(length.to_le_bytes());
+            data.extend((!length).to_le_bytes());
+            data.extend(block);
+        }
+        data.extend(adler32(&raw).to_be_bytes());
+
+        let mut header = Vec::new();
+        header.extend(self.width.to_be_bytes());
+        header.extend(self.height.to_be_bytes());
+        header.extend([8, 2, 0, 0, 0]); // 8-bit RGB, no interlacing
+
+        let mut png = vec![0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+        for (kind, chunk) in [(b"IHDR", header), (b"IDAT", data), (b"IEND", Vec::new())] {
+            png.extend((chunk.len() as u32).to_be_bytes());
+            let start = png.len();
+            png.extend(kind);
+            png.extend(&chunk);
+            let crc = crc32(&png[start..]);
+            png.extend(crc.to_be_bytes());
+        }
+        png
+    }
+
+    /// Writes the frame as PNG if the path ends with `.png`, as PPM otherwise.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let png = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("png"));
+        fs::write(path, if png { self.to_png() } else { self.to_ppm() })
+    }
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), &byte| {
+        let a = (a + byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    b << 16 | a
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u32, |crc, _| {
+            if crc & 1 == 1 { crc >> 1 ^ 0xEDB8_8320 } else { crc >> 1 }
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_packed_pixels() {
+        let mut machine = Machine::new();
+        let framebuffer = Framebuffer { width: 8, height: 2, format: PixelFormat::Color2, ..Framebuffer::default() };
+        machine.write_byte(framebuffer.base + 2, 0b00_01_10_11).unwrap();
+        assert_eq!(framebuffer.size(), 4);
+        assert_eq!((0..4).map(|x| framebuffer.pixel(&machine, x, 1)).collect::<Vec<_>>(), [0, 1, 2, 3]);
+        assert_eq!(framebuffer.frame(&machine).pixels[8 + 3], [0xFF, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn framebuffer_must_fit_in_memory() {
+        assert!(Framebuffer::default().fits());
+        let huge = Framebuffer { width: 100_000, height: 100_000, ..Framebuffer::default() };
+        assert!(!huge.fits());
+        assert!(huge.contains(huge.base + 0x100000) && !huge.contains(huge.base - 1));
+        assert!(!Framebuffer { base: Machine::MAX_ADDRESS, ..Framebuffer::default() }.fits());
+    }
+
+    #[test]
+    fn pixels_outside_of_memory_are_zero() {
+        let machine = Machine::new();
+        let framebuffer = Framebuffer { base: u32::MAX, width: 100_000, height: 100_000, format: PixelFormat::Palette8 };
+        assert_eq!(framebuffer.pixel(&machine, 99_999, 99_999), 0);
+    }
+}
@@ -0,0 +1,86 @@
+use std::collections::VecDeque;
+use crate::machine::float::SicFloat;
+
+///
+/// What an instruction changed, to restore the state before it.
+/// Device input and output cannot be undone.
+///
+#[derive(Debug, Clone, Default)]
+pub struct UndoRecord {
+    /// Previous values of the changed registers, by index.
+    pub(crate) registers: Vec<(usize, u32)>,
+    pub(crate) f_register: Option<SicFloat>,
+    /// Previous values of written bytes, in the order they were written.
+    /// Bytes that were never written before have no value.
+    pub(crate) memory: Vec<(u32, Option<u8>)>,
+    /// Steps executed before the instruction.
+    pub(crate) steps: u64,
+}
+
+impl UndoRecord {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.registers.is_empty() && self.f_register.is_none() && self.memory.is_empty()
+    }
+}
+
+///
+/// Undo records of the most recent instructions, up to the capacity.
+///
+#[derive(Debug)]
+pub struct History {
+    records: VecDeque<UndoRecord>,
+    capacity: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_CAPACITY)
+    }
+}
+
+impl History {
+    pub const DEFAULT_CAPACITY: usize = 10_000;
+
+    pub fn new(capacity: usize) -> Self {
+        Self { records: VecDeque::new(), capacity }
+    }
+
+    /// Adds the record, dropping the oldest one when full.
+    pub fn push(&mut self, record: UndoRecord) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.records.len() >= self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+
+    pub fn pop(&mut self) -> Option<UndoRecord> {
+        self.records.pop_back()
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Sets the capacity, dropping the oldest records that no longer fit.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.records.len() > capacity {
+            self.records.pop_front();
+        }
+    }
+}
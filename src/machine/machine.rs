@@ -1,33 +1,69 @@
-use std::collections::BTreeMap;
-use crate::errors::RegisterError;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::path::PathBuf;
+use crate::errors::{MachineError, RegisterError};
+use crate::machine::float::SicFloat;
+use crate::machine::history::UndoRecord;
 use crate::machine::register::Register;
+use crate::machine::status::StatusWord;
+use crate::machine::watchpoint::{Access, WatchHit, Watchpoints, Width};
+use crate::machine::word::{to_signed, WORD_MASK};
 use crate::device::device_trait::Device;
 use crate::device::file_device::FileDevice;
 use crate::device::std_device::{StdErrDevice, StdInDevice, StdOutDevice};
 
 
 pub struct Machine {
-    registers: [u32; 10],  // One off, but it's easier to index this way
-    memory: BTreeMap<u32, u8>,
+    pub(crate) registers: [u32; 10],  // One off, but it's easier to index this way
+    pub(crate) f_register: SicFloat,  // 48 bits, doesn't fit into registers
+    pub(crate) memory: BTreeMap<u32, u8>,
     pub(crate) devices: BTreeMap<u8, Box<dyn Device>>,
+    /// Devices that replace the default ones and are kept on reset
+    attached: BTreeSet<u8>,
+    /// Where the `NN.dev` files of devices that are not attached are created
+    device_directory: PathBuf,
+    halt_request: Option<u8>,
+    watchpoints: Watchpoints,
+    watch_hits: RefCell<Vec<WatchHit>>,
+    /// Data reads and writes since the log was last taken, when logging is on.
+    access_log: RefCell<Option<Vec<(u32, Access)>>>,
+    undo: Option<UndoRecord>,
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Machine {
     pub const MAX_ADDRESS: u32 = 0x00FF_FFFF;
     pub fn new() -> Self {
+        Self {
+            registers: [0; 10],
+            f_register: SicFloat::default(),
+            memory: BTreeMap::new(),
+            devices: Self::std_devices(),
+            attached: BTreeSet::new(),
+            device_directory: PathBuf::from("."),
+            halt_request: None,
+            watchpoints: Watchpoints::default(),
+            watch_hits: RefCell::new(Vec::new()),
+            access_log: RefCell::new(None),
+            undo: None,
+        }
+    }
+
+    /// Devices 0, 1 and 2 (stdin, stdout and stderr).
+    fn std_devices() -> BTreeMap<u8, Box<dyn Device>> {
         let mut devices: BTreeMap<u8, Box<dyn Device>> = BTreeMap::new();
-        // Set devices 0, 1 and 2 (stdin, stdout and stderr)
         devices.insert(0, Box::new(StdInDevice));
         devices.insert(1, Box::new(StdOutDevice));
         devices.insert(2, Box::new(StdErrDevice));
-
-        let machine = Self {
-            registers: [0; 10],
-            memory: BTreeMap::new(),
-            devices,
-        };
-
-        machine
+        devices
     }
 
 
@@ -39,6 +75,8 @@ impl Machine {
 
     fn reset_registers(&mut self) {
         self.registers = [0; 10];
+        self.f_register = SicFloat::default();
+        self.halt_request = None;
     }
 
     fn reset_memory(&mut self) {
@@ -46,37 +84,46 @@ impl Machine {
     }
 
     fn reset_devices(&mut self) {
-        self.devices.clear();
+        let attached = &self.attached;
+        self.devices.retain(|address, _| attached.contains(address));
+        for (address, device) in Self::std_devices() {
+            self.devices.entry(address).or_insert(device);
+        }
     }
 
     /// Gets register value.
+    /// For the F register, this is the upper 24 bits of the floating point value.
     pub fn get_reg(&self, register: &Register) -> u32 {
-        self.registers[register.to_index()]
+        match register.to_index() {
+            6 => (self.f_register.to_bits() >> 24) as u32,
+            index => self.registers[index],
+        }
     }
 
     /// Gets the value in the F register as a floating point value.
-    pub fn get_f_reg(&self) -> f32 {
-        f32::from_bits(self.registers[6])
+    pub fn get_f_reg(&self) -> SicFloat {
+        self.f_register
     }
 
 
-    /// Sets register value.
+    /// Sets register value, registers are 24 bits wide.
+    /// For the F register, this sets the upper 24 bits and clears the rest.
     pub fn set_reg(&mut self, register: &Register, value: u32) {
-        self.registers[register.to_index()] = value;
+        match register.to_index() {
+            6 => self.f_register = SicFloat::from_bits(((value & WORD_MASK) as u64) << 24),
+            index => self.registers[index] = value & WORD_MASK,
+        }
     }
 
     /// Sets the F register.
-    /// Note: Floating point value is written as bits.
-    pub fn set_f_reg(&mut self, value: f32) {
-        self.registers[6] = value.to_bits();
+    pub fn set_f_reg(&mut self, value: SicFloat) {
+        self.f_register = value;
     }
 
     /// Gets the register from its index.
     pub fn get_reg_from_index(&self, index: u8) -> Result<u32, RegisterError> {
         let reg = Register::from_index(index)?;
-
-        let index = reg.to_index();
-        Ok(self.registers[index])
+        Ok(self.get_reg(&reg))
     }
 
 
@@ -93,7 +140,7 @@ impl Machine {
 
     pub fn inc_x(&mut self) {
         let x = self.get_reg(&Register::X);
-        self.set_reg(&Register::X, x + 1);
+        self.set_reg(&Register::X, x.wrapping_add(1));
     }
 
     pub fn cmp_reg(&mut self, register: &Register, value: u32) {
@@ -101,77 +148,275 @@ impl Machine {
         self.cmp_vals(x, value);
     }
 
+    /// Compares two words as signed values.
     pub fn cmp_vals(&mut self, val1: u32, val2: u32) {
-        self.set_reg(&Register::SW, match val1.cmp(&val2) {
-                    std::cmp::Ordering::Less => 0x10,
-                    std::cmp::Ordering::Equal => 0x00,
-                    std::cmp::Ordering::Greater => 0x01,
-        });
+        self.set_cc(to_signed(val1).cmp(&to_signed(val2)));
+    }
+
+    pub fn get_status(&self) -> StatusWord {
+        StatusWord::from_bits_retain(self.get_reg(&Register::SW))
     }
 
-    pub fn read_byte(&self, address: u32) -> u8 {
+    pub fn set_status(&mut self, status: StatusWord) {
+        self.set_reg(&Register::SW, status.bits());
+    }
+
+    /// Gets the condition code of the last comparison.
+    pub fn get_cc(&self) -> Ordering {
+        self.get_status().cc()
+    }
+
+    /// Sets the condition code to the result of a comparison, keeping the other SW fields.
+    pub fn set_cc(&mut self, ordering: Ordering) {
+        let mut status = self.get_status();
+        status.set_cc(ordering);
+        self.set_status(status);
+    }
+
+    /// Reads a byte without triggering watchpoints.
+    pub fn peek_byte(&self, address: u32) -> Result<u8, MachineError> {
         if address > Self::MAX_ADDRESS {
-            panic!("Address out of bounds: {:#X}", address);
+            return Err(MachineError::MemoryOutOfBounds { address });
         }
 
         match self.memory.get(&address) {
-            Some(value) => *value,
-            None => 0,
+            Some(value) => Ok(*value),
+            None => Ok(0),
         }
     }
 
-    pub fn write_byte(&mut self, address: u32, value: u8) {
+    /// Reads the byte or word at the address without triggering watchpoints.
+    pub(crate) fn peek_value(&self, address: u32, width: Width) -> u32 {
+        (0..width.bytes()).fold(0, |value, i| {
+            value << 8 | self.peek_byte(address.wrapping_add(i)).unwrap_or_default() as u32
+        })
+    }
+
+    pub fn read_byte(&self, address: u32) -> Result<u8, MachineError> {
+        let value = self.peek_byte(address)?;
+        self.watch(address, Access::READ);
+        Ok(value)
+    }
+
+    pub fn write_byte(&mut self, address: u32, value: u8) -> Result<(), MachineError> {
         if address > Self::MAX_ADDRESS {
-            panic!("Address out of bounds: {:#X}", address);
+            return Err(MachineError::MemoryOutOfBounds { address });
+        }
+
+        self.watch(address, Access::WRITE | Access::CHANGE);
+        let previous = self.memory.insert(address, value);
+        if let Some(undo) = &mut self.undo {
+            undo.memory.push((address, previous));
+        }
+        Ok(())
+    }
+
+    pub fn watchpoints(&self) -> &Watchpoints {
+        &self.watchpoints
+    }
+
+    pub fn watchpoints_mut(&mut self) -> &mut Watchpoints {
+        &mut self.watchpoints
+    }
+
+    /// Records the access for the watchpoints containing the address, along with the value before it.
+    /// Each watched byte or word is recorded once, until the hits are taken.
+    fn watch(&self, address: u32, access: Access) {
+        if let Some(log) = self.access_log.borrow_mut().as_mut() {
+            log.push((address, access & (Access::READ | Access::WRITE)));
         }
+        if self.watchpoints.is_empty() {
+            return;
+        }
+
+        let mut hits = self.watch_hits.borrow_mut();
+        for watchpoint in self.watchpoints.matching(address, access) {
+            let Some(unit) = watchpoint.unit(address) else {
+                continue;
+            };
+            let access = watchpoint.access & access;
+            if hits.iter().any(|hit| hit.watchpoint == watchpoint.address && hit.address == unit && hit.access == access) {
+                continue;
+            }
+
+            let old = self.peek_value(unit, watchpoint.width);
+            hits.push(WatchHit {
+                watchpoint: watchpoint.address,
+                address: unit,
+                width: watchpoint.width,
+                access,
+                pc: 0,
+                old,
+                new: old,
+            });
+        }
+    }
+
+    /// Takes the watchpoint hits of memory accesses since the last call.
+    pub(crate) fn take_watch_hits(&mut self) -> Vec<WatchHit> {
+        self.watch_hits.take()
+    }
+
+    /// Starts or stops logging data reads and writes.
+    pub(crate) fn set_access_log(&mut self, enabled: bool) {
+        *self.access_log.get_mut() = enabled.then(Vec::new);
+    }
 
-        self.memory.insert(address, value);
+    pub(crate) fn take_accesses(&mut self) -> Vec<(u32, Access)> {
+        self.access_log.get_mut().as_mut().map(std::mem::take).unwrap_or_default()
     }
 
-    pub fn read_word(&self, address: u32) -> u32 {
+    pub fn read_word(&self, address: u32) -> Result<u32, MachineError> {
         let mut word = 0;
 
         for i in 0..3 {
-            let byte = self.read_byte(address + i);
+            let byte = self.read_byte(address.wrapping_add(i))?;
             word <<= 8;
             word |= byte as u32;
         }
 
-        word
+        Ok(word)
     }
 
-    pub fn write_word(&mut self, address: u32, value: u32) {
-        let address = address + 2;
+    pub fn write_word(&mut self, address: u32, value: u32) -> Result<(), MachineError> {
         for i in 0..3 {
-            let byte = (value >> (8 * i)) as u8;
-            self.write_byte(address - i, byte);
+            let byte = (value >> (8 * (2 - i))) as u8;
+            self.write_byte(address.wrapping_add(i), byte)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a 6 byte floating point value.
+    pub fn read_float(&self, address: u32) -> Result<SicFloat, MachineError> {
+        let mut bytes = [0; SicFloat::BYTES];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = self.read_byte(address.wrapping_add(i as u32))?;
+        }
+        Ok(SicFloat::from_bytes(bytes))
+    }
+
+    /// Writes a 6 byte floating point value.
+    pub fn write_float(&mut self, address: u32, value: SicFloat) -> Result<(), MachineError> {
+        for (i, byte) in value.to_bytes().into_iter().enumerate() {
+            self.write_byte(address.wrapping_add(i as u32), byte)?;
+        }
+        Ok(())
+    }
+
+    /// Starts recording what the next instruction changes.
+    pub(crate) fn begin_undo(&mut self) {
+        self.undo = Some(UndoRecord {
+            registers: self.registers.iter().copied().enumerate().collect(),
+            f_register: Some(self.f_register),
+            ..Default::default()
+        });
+    }
+
+    /// Stops recording, keeping only the registers that changed.
+    pub(crate) fn end_undo(&mut self) -> Option<UndoRecord> {
+        let mut record = self.undo.take()?;
+        record.registers.retain(|(index, value)| self.registers[*index] != *value);
+        record.f_register = record.f_register.filter(|f| *f != self.f_register);
+        Some(record)
+    }
+
+    /// Restores the state before the recorded instruction.
+    pub(crate) fn undo(&mut self, record: &UndoRecord) {
+        for (index, value) in &record.registers {
+            self.registers[*index] = *value;
+        }
+        if let Some(f) = record.f_register {
+            self.f_register = f;
+        }
+        // Restore in reverse, so bytes written twice get their oldest value
+        for (address, value) in record.memory.iter().rev() {
+            match value {
+                Some(value) => self.memory.insert(*address, *value),
+                None => self.memory.remove(address),
+            };
         }
     }
 
-    pub fn read_float(&self, address: u32) -> f32 {
-        let word = self.read_word(address);
-        f32::from_bits(word)
+    /// Asks the runner to halt the program with the given code, after the current instruction.
+    pub fn request_halt(&mut self, code: u8) {
+        self.halt_request = Some(code);
     }
 
-    pub fn write_float(&mut self, address: u32, value: f32) {
-        let word = value.to_bits();
-        self.write_word(address, word);
+    pub(crate) fn take_halt_request(&mut self) -> Option<u8> {
+        self.halt_request.take()
     }
 
-    ///
-    pub fn get_device(&mut self, address: u8) -> &mut Box<dyn Device> {
+    /// Gets the device at the given address.
+    pub fn get_device(&mut self, address: u8) -> Result<&mut Box<dyn Device>, MachineError> {
         // If device exists, return it, otherwise create a new one
-        self.devices.entry(address).or_insert(Box::new(FileDevice::new(address)))
+        match self.devices.entry(address) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => {
+                let device = FileDevice::in_directory(&self.device_directory, address)
+                    .map_err(|e| device_error(address, e))?;
+                Ok(entry.insert(Box::new(device)))
+            }
+        }
+    }
+
+    /// Replaces the device at the given address. Attached devices are kept when the machine is reset.
+    pub fn attach_device(&mut self, address: u8, device: Box<dyn Device>) {
+        self.devices.insert(address, device);
+        self.attached.insert(address);
+    }
+
+    /// Sets the directory of the `NN.dev` files created for devices on first use.
+    pub fn set_device_directory(&mut self, directory: impl Into<PathBuf>) {
+        self.device_directory = directory.into();
+    }
+
+    /// Tests whether the device is ready.
+    pub fn test_device(&mut self, address: u8) -> Result<bool, MachineError> {
+        Ok(self.get_device(address)?.test())
+    }
+
+    /// Reads a byte from the device.
+    pub fn read_device(&mut self, address: u8) -> Result<u8, MachineError> {
+        self.get_device(address)?.read().map_err(|e| device_error(address, e))
+    }
+
+    /// Writes a byte to the device.
+    pub fn write_device(&mut self, address: u8, value: u8) -> Result<(), MachineError> {
+        self.get_device(address)?.write(value).map_err(|e| device_error(address, e))
     }
 
 
-    /// Gets current instruction from memory and increments PC.
-    /// Returns the instruction at the memory address pointed to by PC.
-    pub(crate) fn fetch(&mut self) -> u8 {
-        let pc = self.get_reg(&Register::PC);
-        let value = self.read_byte(pc);
-        self.set_reg(&Register::PC, pc + 1);
+}
+
+fn device_error(device: u8, error: io::Error) -> MachineError {
+    MachineError::Device { device, message: error.to_string() }
+}
 
-        value
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undo_restores_registers_and_memory() {
+        let mut machine = Machine::new();
+        machine.write_byte(0x100, 0x11).unwrap();
+        machine.set_reg(&Register::A, 7);
+        let memory = machine.memory.clone();
+
+        machine.begin_undo();
+        machine.set_reg(&Register::A, 8);
+        machine.set_f_reg(SicFloat::from_f64(1.0));
+        // Written twice, the oldest value must come back
+        machine.write_byte(0x100, 0x22).unwrap();
+        machine.write_byte(0x100, 0x33).unwrap();
+        // Not written before, must be unset again
+        machine.write_word(0x200, 0x123456).unwrap();
+        let record = machine.end_undo().unwrap();
+        assert_eq!(record.registers, [(Register::A.to_index(), 7)]);
+
+        machine.undo(&record);
+        assert_eq!(machine.get_reg(&Register::A), 7);
+        assert_eq!(machine.get_f_reg(), SicFloat::default());
+        assert_eq!(machine.memory, memory);
     }
 }
@@ -1,3 +1,16 @@
+pub mod breakpoint;
+pub mod config;
+pub mod coverage;
+pub mod float;
+pub mod framebuffer;
+pub mod history;
 pub mod machine;
+pub mod profiler;
 pub mod register;
 pub mod runner;
+pub mod screen;
+pub mod snapshot;
+pub mod status;
+pub mod trace;
+pub mod watchpoint;
+pub mod word;
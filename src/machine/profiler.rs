@@ -0,0 +1,194 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use crate::loader::symbols::SymbolTable;
+use crate::machine::machine::Machine;
+use crate::opcode::decoder::Decoded;
+use crate::opcode::disassembler::disassemble_with_symbols;
+use crate::opcode::opcode::Opcode;
+
+///
+/// Executions and cycles of one address, label or opcode.
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Count {
+    pub executions: u64,
+    pub cycles: u64,
+}
+
+impl Count {
+    fn add(&mut self, cycles: u64) {
+        self.executions += 1;
+        self.cycles += cycles;
+    }
+}
+
+///
+/// Counts executed instructions per address and opcode, and calls per JSUB target.
+///
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+    addresses: BTreeMap<u32, Count>,
+    opcodes: BTreeMap<u8, Count>,
+    calls: BTreeMap<u32, u64>,
+    total: Count,
+}
+
+impl Profiler {
+    /// Estimated cost of the instruction: one cycle per byte fetched,
+    /// and one more for the extra memory access of indirect addressing.
+    pub fn cycles(decoded: &Decoded) -> u64 {
+        let indirect = match decoded {
+            Decoded::Format34(f) => f.indirect(),
+            _ => false,
+        };
+        decoded.length() as u64 + indirect as u64
+    }
+
+    /// Counts an executed instruction. `next` is PC after it, the target of a JSUB.
+    pub(crate) fn record(&mut self, address: u32, decoded: &Decoded, next: u32) {
+        let cycles = Self::cycles(decoded);
+        self.addresses.entry(address).or_default().add(cycles);
+        self.opcodes.entry(decoded.opcode()).or_default().add(cycles);
+        self.total.add(cycles);
+        if decoded.opcode() == Opcode::JSUB {
+            *self.calls.entry(next).or_default() += 1;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn total(&self) -> Count {
+        self.total
+    }
+
+    pub fn addresses(&self) -> &BTreeMap<u32, Count> {
+        &self.addresses
+    }
+
+    pub fn opcodes(&self) -> &BTreeMap<u8, Count> {
+        &self.opcodes
+    }
+
+    /// Number of JSUB calls, by target address.
+    pub fn calls(&self) -> &BTreeMap<u32, u64> {
+        &self.calls
+    }
+
+    /// Builds a report with the `top` most executed addresses.
+    /// Addresses are attributed to the closest symbol at or before them.
+    pub fn report(&self, machine: &Machine, symbols: &SymbolTable, top: usize) -> ProfileReport {
+        let label_of = |address: u32| symbols.nearest(address).map(|(name, _)| name.to_string());
+
+        let mut addresses = self.addresses.iter().map(|(a, c)| (*a, *c)).collect::<Vec<_>>();
+        addresses.sort_by(|(a1, c1), (a2, c2)| c2.executions.cmp(&c1.executions).then(a1.cmp(a2)));
+        let hot_spots = addresses
+            .into_iter()
+            .take(top)
+            .map(|(address, count)| HotSpot {
+                address,
+                text: disassemble_with_symbols(machine, address, symbols).text,
+                label: label_of(address),
+                count,
+            })
+            .collect();
+
+        let mut opcodes = self
+            .opcodes
+            .iter()
+            .map(|(opcode, count)| (Opcode::mnemonic(*opcode).unwrap_or_default(), *count))
+            .collect::<Vec<_>>();
+        opcodes.sort_by(|(m1, c1), (m2, c2)| c2.executions.cmp(&c1.executions).then(m1.cmp(m2)));
+
+        let mut totals = BTreeMap::<String, Count>::new();
+        for (address, count) in &self.addresses {
+            if let Some(label) = label_of(*address) {
+                let total = totals.entry(label).or_default();
+                total.executions += count.executions;
+                total.cycles += count.cycles;
+            }
+        }
+        let mut totals = totals.into_iter().collect::<Vec<_>>();
+        totals.sort_by(|(l1, c1), (l2, c2)| c2.executions.cmp(&c1.executions).then(l1.cmp(l2)));
+
+        let mut calls = self
+            .calls
+            .iter()
+            .map(|(address, calls)| {
+                let label = symbols.name(*address).map(str::to_string);
+                (*address, label, *calls)
+            })
+            .collect::<Vec<_>>();
+        calls.sort_by(|(a1, _, c1), (a2, _, c2)| c2.cmp(c1).then(a1.cmp(a2)));
+
+        ProfileReport { total: self.total, hot_spots, opcodes, labels: totals, calls }
+    }
+}
+
+///
+/// Most executed instruction, with the label it belongs to.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct HotSpot {
+    pub address: u32,
+    pub text: String,
+    pub label: Option<String>,
+    pub count: Count,
+}
+
+///
+/// Summary of a profile, sorted by executions.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileReport {
+    pub total: Count,
+    pub hot_spots: Vec<HotSpot>,
+    pub opcodes: Vec<(&'static str, Count)>,
+    /// Totals per label, when symbols are known.
+    pub labels: Vec<(String, Count)>,
+    /// JSUB calls by target address, with the label at the target.
+    pub calls: Vec<(u32, Option<String>, u64)>,
+}
+
+impl ProfileReport {
+    fn percent(&self, executions: u64) -> f64 {
+        executions as f64 * 100.0 / self.total.executions.max(1) as f64
+    }
+}
+
+impl Display for ProfileReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Instructions: {}, cycles: {}", self.total.executions, self.total.cycles)?;
+
+        writeln!(f, "\nTop addresses:")?;
+        for spot in &self.hot_spots {
+            let label = spot.label.as_deref().unwrap_or("");
+            writeln!(f, "  {:06X}  {:<8} {:<20} {:>10} {:>6.1}% {:>10} cycles",
+                     spot.address, label, spot.text, spot.count.executions,
+                     self.percent(spot.count.executions), spot.count.cycles)?;
+        }
+
+        writeln!(f, "\nOpcodes:")?;
+        for (mnemonic, count) in &self.opcodes {
+            writeln!(f, "  {mnemonic:<8} {:>10} {:>6.1}% {:>10} cycles",
+                     count.executions, self.percent(count.executions), count.cycles)?;
+        }
+
+        if !self.labels.is_empty() {
+            writeln!(f, "\nLabels:")?;
+            for (label, count) in &self.labels {
+                writeln!(f, "  {label:<8} {:>10} {:>6.1}% {:>10} cycles",
+                         count.executions, self.percent(count.executions), count.cycles)?;
+            }
+        }
+
+        if !self.calls.is_empty() {
+            writeln!(f, "\nCalls:")?;
+            for (address, label, calls) in &self.calls {
+                writeln!(f, "  {address:06X}  {:<8} {calls:>10}", label.as_deref().unwrap_or(""))?;
+            }
+        }
+        Ok(())
+    }
+}
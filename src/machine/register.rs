@@ -4,7 +4,7 @@ use crate::errors::RegisterError;
 /// Represents a register in the SIC/XE machine.
 /// Does not include the F register, which is a floating point register.
 ///
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Register(pub &'static str, pub u8);
 
 impl Register {
@@ -35,6 +35,13 @@ impl Register {
         }
     }
 
+    /// Gets the register by its name (case-insensitive).
+    pub fn from_name(name: &str) -> Option<Self> {
+        (0..10)
+            .filter_map(|index| Self::from_index(index).ok())
+            .find(|register| register.0.eq_ignore_ascii_case(name))
+    }
+
     pub fn to_index(&self) -> usize {
         self.1 as usize
     }
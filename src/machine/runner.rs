@@ -1,17 +1,98 @@
-use std::fs::File;
-use std::io::{BufRead, BufReader, Read};
+use std::fs;
+use std::fmt::{Display, Formatter};
+use std::io::Read;
 use std::ops::Sub;
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
+use crate::asm::assembler::assemble_file;
+use crate::asm::source_map::SourceMap;
+use crate::device::device_trait::Device;
+use crate::errors::{ConfigError, ExecError, LoadError, MachineError, SimulatorError, SnapshotError};
+use crate::loader::loader::{load, LoadMap, LoadedSection};
+use crate::loader::symbols::SymbolTable;
+use crate::machine::breakpoint::Breakpoints;
+use crate::machine::config::MachineConfig;
+use crate::machine::coverage::Coverage;
+use crate::machine::framebuffer::{Frame, Framebuffer};
+use crate::machine::history::History;
 use crate::machine::machine::Machine;
+use crate::machine::profiler::{ProfileReport, Profiler};
 use crate::machine::register::Register;
-use crate::opcode::format::{Format1, Format2, Format34};
+use crate::machine::screen::TextScreen;
+use crate::machine::snapshot::Snapshot;
+use crate::machine::trace::{TraceEntry, Tracer};
+use crate::machine::watchpoint::{Access, WatchHit, Watchpoints};
+use crate::object::parser::parse_object;
+use crate::object::program::ObjectProgram;
+use crate::opcode::decoder::{decode, Decoded};
+use crate::opcode::disassembler::disassemble_with_symbols;
 use crate::opcode::instruction::Instruction;
-use bitflags::parser::ParseHex;
+use crate::opcode::opcode::Opcode;
+
+///
+/// Reason why the program stopped executing.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum HaltReason {
+    /// Jump to the same instruction, as in `halt J halt`.
+    SelfJump { address: u32 },
+    /// Invalid opcode outside the loaded sections.
+    EndOfProgram { address: u32 },
+    /// `SVC n` instruction, with n as the code.
+    Stop { code: u8 },
+    /// The step limit was reached.
+    StepLimit { steps: u64 },
+}
+
+impl Display for HaltReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HaltReason::SelfJump { address } => write!(f, "jump to itself at {address:06X}"),
+            HaltReason::EndOfProgram { address } => write!(f, "end of program at {address:06X}"),
+            HaltReason::Stop { code } => write!(f, "stopped with code {code}"),
+            HaltReason::StepLimit { steps } => write!(f, "step limit of {steps} reached"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum State {
+    Stopped,
+    Running,
+    Halted(HaltReason),
+}
 
 pub struct Runner {
     machine: Machine,
     frequency: u64,
     last_ex: Option<Instant>,
+    load_map: Option<LoadMap>,
+    halted: Option<HaltReason>,
+    steps: u64,
+    max_steps: Option<u64>,
+    breakpoints: Breakpoints,
+    breakpoint_hit: Option<u32>,
+    watch_hits: Vec<WatchHit>,
+    /// Subroutine returns left before stopping, when stepping over or out of a subroutine
+    pending_returns: Option<u32>,
+    returned: bool,
+    history: History,
+    tracer: Option<Tracer>,
+    profiler: Option<Profiler>,
+    coverage: Option<Coverage>,
+    symbols: SymbolTable,
+    text_screen: Option<TextScreen>,
+    framebuffer: Option<Framebuffer>,
+    /// Problems that did not stop the last load
+    warnings: Vec<String>,
+    /// Files the assembler wrote during the last load
+    written_files: Vec<PathBuf>,
+}
+
+impl Default for Runner {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Runner {
@@ -20,6 +101,24 @@ impl Runner {
             machine: Machine::new(),
             frequency: 1_000_000,
             last_ex: None,
+            load_map: None,
+            halted: None,
+            steps: 0,
+            max_steps: None,
+            breakpoints: Breakpoints::default(),
+            breakpoint_hit: None,
+            watch_hits: Vec::new(),
+            pending_returns: None,
+            returned: false,
+            history: History::default(),
+            tracer: None,
+            profiler: None,
+            coverage: None,
+            symbols: SymbolTable::default(),
+            text_screen: Some(TextScreen::default()),
+            framebuffer: Some(Framebuffer::default()),
+            warnings: Vec::new(),
+            written_files: Vec::new(),
         }
     }
 
@@ -31,99 +130,290 @@ impl Runner {
         self.last_ex.is_some()
     }
 
-    pub fn load_file(&mut self, mut file: &File) -> Result<(), String> {
-        // Check running
-        if self.running() {
-            // Todo - auto stop machine & clear memory
-            //return Err("Please stop machine first".into());
+    pub fn state(&self) -> State {
+        match &self.halted {
+            Some(reason) => State::Halted(reason.clone()),
+            None if self.running() => State::Running,
+            None => State::Stopped,
         }
-        self.machine.reset();
+    }
 
-        // Load file into memory
-        let mut buffer = [0; 1];
-        let mut char_buffer6 = [0; 6];
+    /// Gets the reason the program halted, if it did.
+    pub fn halted(&self) -> Option<&HaltReason> {
+        self.halted.as_ref()
+    }
 
-        if file.read(&mut buffer).is_ok() {
-            // We should have read 'H'
-            if buffer[0] != 'H' as u8 {
-                return Err(format!("Unexpected char, expected 'H', found {}.", buffer[0] as char));
+    /// Gets the number of instructions executed since the programs were loaded.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn breakpoints(&self) -> &Breakpoints {
+        &self.breakpoints
+    }
+
+    pub fn breakpoints_mut(&mut self) -> &mut Breakpoints {
+        &mut self.breakpoints
+    }
+
+    /// Gets the address of the breakpoint the last instruction stopped at.
+    pub fn breakpoint_hit(&self) -> Option<u32> {
+        self.breakpoint_hit
+    }
+
+    pub fn watchpoints(&self) -> &Watchpoints {
+        self.machine.watchpoints()
+    }
+
+    pub fn watchpoints_mut(&mut self) -> &mut Watchpoints {
+        self.machine.watchpoints_mut()
+    }
+
+    /// Gets the watchpoints the last instruction triggered.
+    pub fn watch_hits(&self) -> &[WatchHit] {
+        &self.watch_hits
+    }
+
+    /// Checks whether the last instruction hit a breakpoint or a watchpoint.
+    pub fn paused(&self) -> bool {
+        self.breakpoint_hit.is_some() || !self.watch_hits.is_empty() || self.returned
+    }
+
+    /// Resolves a hexadecimal address or an external symbol of the loaded programs.
+    pub fn resolve_address(&self, text: &str) -> Option<u32> {
+        self.symbols.resolve(text)
+    }
+
+    /// Limits the number of instructions executed, the program halts when the limit is reached.
+    pub fn set_max_steps(&mut self, max_steps: Option<u64>) {
+        self.max_steps = max_steps;
+    }
+
+    /// Sets the number of instructions executed per second.
+    pub fn set_frequency(&mut self, frequency: u64) {
+        self.frequency = frequency.max(1);
+    }
+
+    /// Loads the object programs from the reader at the address in the first H record.
+    pub fn load_file(&mut self, mut file: impl Read) -> Result<(), LoadError> {
+        let mut source = String::new();
+        file.read_to_string(&mut source)?;
+        let programs = parse_object(&source)?;
+
+        self.load_programs(&programs, None)
+    }
+
+    /// Loads the files at the base address. Assembly sources (`.asm`) are assembled first,
+    /// other files are read as object programs. Labels and source lines of an object file are read
+    /// from the symbol file (`.sym`) and listing (`.lst`) with the same name, if there are any.
+    /// Those that cannot be read, like listings of other assemblers, are skipped with a warning.
+    /// The files written by the assembler are listed by [`Self::written_files`].
+    pub fn load_paths(&mut self, paths: &[impl AsRef<Path>], base: Option<u32>) -> Result<(), LoadError> {
+        self.warnings.clear();
+        self.written_files.clear();
+        let mut programs = Vec::new();
+        for path in paths {
+            let path = path.as_ref();
+            if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("asm")) {
+                let (program, written) = assemble_file(path)?;
+                programs.push(program);
+                self.written_files.extend(written);
+            } else {
+                let source = fs::read_to_string(path)
+                    .map_err(|e| LoadError::Io(format!("{}: {e}", path.display())))?;
+                let mut parsed = parse_object(&source)?;
+                let symbols_path = path.with_extension("sym");
+                let symbols = self.read_side_file(&symbols_path, SymbolTable::parse);
+                if let (Some(program), Some(symbols)) = (parsed.first_mut(), symbols) {
+                    program.labels = symbols;
+                }
+                let listing_path = path.with_extension("lst");
+                let source = self.read_side_file(&listing_path, SourceMap::parse_listing);
+                if let (Some(program), Some(mut source)) = (parsed.first_mut(), source) {
+                    let asm_path = path.with_extension("asm");
+                    source.path = Some(if asm_path.exists() { asm_path } else { listing_path });
+                    program.source = Some(source);
+                }
+                programs.extend(parsed);
             }
         }
 
-        // Read 6 chars into prg_name
-        if let Err(e) = file.read_exact(&mut char_buffer6) {
-            return Err(e.to_string())
-        }
+        self.load_programs(&programs, base)
+    }
 
-        if let Err(e) = file.read_exact(&mut char_buffer6) {
-            return Err(e.to_string())
+    /// Parses a file that accompanies an object file. A missing file gives `None`,
+    /// one that cannot be read or parsed is also skipped, with a warning.
+    fn read_side_file<T>(&mut self, path: &Path, parse: impl Fn(&str) -> Result<T, LoadError>) -> Option<T> {
+        if !path.exists() {
+            return None;
         }
-
-        let start_addr = u32::from_str_radix(String::from_utf8(Vec::from(char_buffer6)).unwrap().as_str(), 16);
-        if let Err(e) = start_addr {
-            return Err(e.to_string());
+        let parsed = fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| parse(&text).map_err(|e| e.to_string()));
+        match parsed {
+            Ok(value) => Some(value),
+            Err(e) => {
+                self.warnings.push(format!("Skipped {}: {e}", path.display()));
+                None
+            }
         }
+    }
 
-        let start_addr = start_addr.unwrap();
+    /// Problems that did not stop the last load, like a listing that could not be parsed.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
 
-        // Read prg len
-        if let Err(e) = file.read_exact(&mut char_buffer6) {
-            return Err(e.to_string())
-        }
+    /// Object, symbol and listing files written when assembling the sources of the last load.
+    pub fn written_files(&self) -> &[PathBuf] {
+        &self.written_files
+    }
 
-        let prg_len = u32::from_str_radix(String::from_utf8(Vec::from(char_buffer6)).unwrap().as_str(), 16);
+    /// Restores a single snapshot file (`.snap`), or loads the files like [`Self::load_paths`].
+    pub fn open_paths(&mut self, paths: &[impl AsRef<Path>], base: Option<u32>) -> Result<(), SimulatorError> {
+        match paths {
+            [path] if path.as_ref().extension().is_some_and(|ext| ext.eq_ignore_ascii_case(Snapshot::EXTENSION)) => {
+                Ok(self.load_snapshot(path)?)
+            }
+            _ => Ok(self.load_paths(paths, base)?),
+        }
+    }
 
-        if let Err(e) = prg_len {
-            return Err(e.to_string());
+    /// Links the object programs and loads them at the base address,
+    /// or at the address in the first H record if no base address is given.
+    pub fn load_programs(&mut self, programs: &[ObjectProgram], base: Option<u32>) -> Result<(), LoadError> {
+        // Check running
+        if self.running() {
+            // Todo - auto stop machine & clear memory
+            //return Err("Please stop machine first".into());
+        }
+        self.machine.reset();
+        self.load_map = None;
+        self.symbols = SymbolTable::default();
+        self.halted = None;
+        self.steps = 0;
+        self.pending_returns = None;
+        self.returned = false;
+        self.history.clear();
+        if let Some(profiler) = &mut self.profiler {
+            profiler.clear();
         }
 
-        let _prg_len = prg_len.unwrap();
+        let map = load(&mut self.machine, programs, base);
+        // Loading does not trigger watchpoints
+        self.machine.take_watch_hits();
+        self.machine.take_accesses();
+        let map = map?;
 
-        let reader = BufReader::new(file);
+        // Set PC value to the first instruction address
+        self.machine.set_reg(&Register::PC, map.entry);
+        self.load_map = Some(map);
+        self.reset_symbols();
+        self.reset_coverage();
 
-        let mut end_record = None;
-        for line in reader.lines() {
-            if let Ok(line) = line {
-                // First char is either T or E
-                if line.starts_with('T') {
-                    let addr_start = u32::from_str_radix(&line[1..7], 16).unwrap();
-                    let mut i = 0;
-                    
-                    // Leave 2 bits out
-                    let mut instructions = line[9..line.len()].chars().peekable();
+        Ok(())
+    }
 
-                    while instructions.peek().is_some() {
-                        let instruction: String = instructions.by_ref().take(2).collect();
-                        let instruction = u8::parse_hex(instruction.as_str()).unwrap();
+    /// Symbols of the loaded programs: labels, external symbols and section names.
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
 
-                        // Write instruction to machine
-                        self.machine.write_byte(start_addr + addr_start + i, instruction);
-                        i += 1;
-                    }
+    /// Adds the symbols of the symbol file, e.g. for object files assembled elsewhere.
+    /// Addresses are used as they are; symbols are reset when a program is loaded.
+    pub fn load_symbols(&mut self, path: impl AsRef<Path>) -> Result<(), LoadError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|e| LoadError::Io(format!("{}: {e}", path.display())))?;
+        self.symbols.extend(&SymbolTable::parse(&text)?);
+        Ok(())
+    }
 
-                } else if line.starts_with('E') {
-                    if end_record.is_some() {
-                        return Err("Expected only 1 E record.".into());
-                    }
-                    end_record = Some(u32::parse_hex(&line[1..line.len()]).unwrap())
-                }
-            } else {
-                return Err(line.err().unwrap().to_string())
+    /// Rebuilds the symbols from the load map. Labels are preferred over external symbols and section names.
+    fn reset_symbols(&mut self) {
+        self.symbols = SymbolTable::default();
+        let Some(map) = &self.load_map else {
+            return;
+        };
+        for section in &map.sections {
+            self.symbols.extend(&section.labels);
+        }
+        for (name, address) in &map.symbols {
+            if !map.sections.iter().any(|section| &section.name == name) {
+                self.symbols.insert(name, *address);
             }
         }
-        
-        if end_record.is_none() {
-            return Err("Expected E record at th end of file.".into());
+        for section in &map.sections {
+            self.symbols.insert(&section.name, section.address);
         }
-        
-        let end_record = end_record.unwrap();
-        // Set PC value to the first instruction address
-        self.machine.set_reg(&Register::PC, end_record);
+    }
+
+    /// Gets the load map of the last loaded programs.
+    pub fn load_map(&self) -> Option<&LoadMap> {
+        self.load_map.as_ref()
+    }
 
+    /// Finds the loaded section containing the address.
+    pub fn section_at(&self, address: u32) -> Option<&LoadedSection> {
+        self.load_map.as_ref().and_then(|map| {
+            map.sections
+                .iter()
+                .find(|section| (section.address..section.address + section.length).contains(&address))
+        })
+    }
+
+    /// Captures the machine, load map, frequency and step count.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            frequency: self.frequency,
+            steps: self.steps,
+            load_map: self.load_map.clone(),
+            ..Snapshot::capture(&self.machine)
+        }
+    }
+
+    /// Restores the state of the snapshot. Devices are reopened and moved to their saved positions.
+    /// Breakpoints and watchpoints are kept, the undo history is cleared.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), SnapshotError> {
+        self.machine.reset();
+        self.machine.registers = snapshot.registers;
+        self.machine.f_register = snapshot.f_register;
+        self.machine.memory = snapshot.memory.clone();
+        for (&number, &position) in &snapshot.devices {
+            let device = self.machine.get_device(number).map_err(|e| SnapshotError::Io(e.to_string()))?;
+            device
+                .seek(position)
+                .map_err(|e| SnapshotError::Io(format!("Device {number:02X}: {e}")))?;
+        }
 
+        self.frequency = snapshot.frequency.max(1);
+        self.steps = snapshot.steps;
+        self.load_map = snapshot.load_map.clone();
+        self.reset_symbols();
+        self.halted = None;
+        self.breakpoint_hit = None;
+        self.watch_hits.clear();
+        self.pending_returns = None;
+        self.returned = false;
+        self.history.clear();
+        if let Some(profiler) = &mut self.profiler {
+            profiler.clear();
+        }
+        self.reset_coverage();
+        Ok(())
+    }
+
+    pub fn save_snapshot(&self, path: impl AsRef<Path>) -> Result<(), SnapshotError> {
+        fs::write(path, self.snapshot().to_string())?;
         Ok(())
     }
 
+    pub fn load_snapshot(&mut self, path: impl AsRef<Path>) -> Result<(), SnapshotError> {
+        self.warnings.clear();
+        self.written_files.clear();
+        let snapshot = Snapshot::parse(&fs::read_to_string(path)?)?;
+        self.restore(&snapshot)
+    }
+
     pub fn stop(&mut self) {
         self.last_ex = None;
     }
@@ -133,7 +423,7 @@ impl Runner {
     }
 
 
-    pub fn try_step(&mut self) -> Result<(), String> {
+    pub fn try_step(&mut self) -> Result<(), ExecError> {
         let now = Instant::now();
 
         if let Some(prev) = self.last_ex {
@@ -146,49 +436,411 @@ impl Runner {
     }
 
 
-    /// Execute instruction at PC
-    fn step(&mut self) -> Result<(), String> {
-        let first_byte = self.machine.fetch();
-        let opcode = first_byte & 0xFC;
+    /// Executes the instruction at PC, regardless of the frequency.
+    /// Does nothing once the program has halted.
+    pub fn step(&mut self) -> Result<(), ExecError> {
+        self.breakpoint_hit = None;
+        self.watch_hits.clear();
+        self.returned = false;
+        if self.halted.is_some() {
+            return Ok(());
+        }
+        if self.max_steps.is_some_and(|max| self.steps >= max) {
+            self.halted = Some(HaltReason::StepLimit { steps: self.steps });
+            return Ok(());
+        }
 
-        let instruction: Box<dyn Instruction> = match first_byte {
-            // Format 1
-            0xC0..=0xC8 | 0xF0..=0xF8 => Box::new(Format1 { opcode }),
-            // Format 2
-            0x90..=0xB8 => {
-                let registers = self.machine.fetch();
+        let pc = self.machine.get_reg(&Register::PC);
+        self.machine.begin_undo();
+        let instruction = self.fetch();
+        let trace = match (&self.tracer, &instruction) {
+            (Some(tracer), Ok(decoded)) if tracer.filter.matches(pc, decoded.opcode()) => {
+                Some(self.trace_entry(pc, decoded))
+            }
+            _ => None,
+        };
 
-                let r1 = (registers >> 4) & 0xF;
-                let r1 = Register::from_index(r1)?;
-                let r2 = registers & 0xF;
-                let r2 = Register::from_index(r2)?;
+        // Raw bytes of the instruction, for error reporting
+        let length = self.machine.get_reg(&Register::PC).wrapping_sub(pc).max(1);
+        let bytes = (0..length)
+            .filter_map(|i| self.machine.peek_byte(pc.wrapping_add(i)).ok())
+            .collect::<Vec<_>>();
 
-                let instruct = Format2 { opcode, r1, r2 };
-                Box::new(instruct)
+        self.last_ex = Some(Instant::now());
+        let decoded = instruction.as_ref().ok().copied();
+        let result = instruction.and_then(|instruction| instruction.exec(&mut self.machine));
+        if let (Some(profiler), Some(decoded), Ok(())) = (&mut self.profiler, &decoded, &result) {
+            profiler.record(pc, decoded, self.machine.get_reg(&Register::PC));
+        }
+        let accesses = self.machine.take_accesses();
+        if let Some(coverage) = &mut self.coverage {
+            for (address, access) in accesses {
+                coverage.record_access(address, access);
             }
-            // Format 3 / 4
-            0x00..=0x88 | 0xD0..=0xE0 => {
-                let mut flags = self.machine.fetch();
-                let adplus = flags & 0xF;
-                flags >>= 4;
+            if let (Some(decoded), Ok(())) = (&decoded, &result) {
+                let next = pc.wrapping_add(decoded.length());
+                let taken = matches!(decoded.opcode(), Opcode::JEQ | Opcode::JGT | Opcode::JLT)
+                    .then(|| self.machine.get_reg(&Register::PC) != next);
+                coverage.record_execution(pc, taken);
+            }
+        }
+        self.collect_watch_hits(pc);
+        if let Some(mut record) = self.machine.end_undo() {
+            if let (Some(mut entry), Some(tracer), Ok(())) = (trace, &mut self.tracer, &result) {
+                entry.set_changes(&record, &self.machine);
+                tracer.record(entry);
+            }
+            // Faulting instructions are kept only if they changed something
+            if result.is_ok() || !record.is_empty() {
+                record.steps = self.steps;
+                self.history.push(record);
+            }
+        }
 
-                // Merge 2 bits from opcode
-                flags |= (first_byte & 0b11) << 4;
-                let extended = flags & 1 != 0;
+        match result {
+            Err(MachineError::InvalidOpcode { .. }) if !self.in_program(pc) => {
+                // Ran past the end of the program
+                self.halted = Some(HaltReason::EndOfProgram { address: pc });
+                self.pending_returns = None;
+                return Ok(());
+            }
+            Err(kind) => {
+                self.pending_returns = None;
+                return Err(ExecError { pc, bytes, kind });
+            }
+            Ok(()) => self.steps += 1,
+        }
 
-                let mut addr = (((adplus as u16) << 8) | self.machine.fetch() as u16) as u32;
+        if let (Some(pending), Some(decoded)) = (self.pending_returns, &decoded) {
+            let pending = match decoded.opcode() {
+                Opcode::JSUB => pending + 1,
+                Opcode::RSUB => pending.saturating_sub(1),
+                _ => pending,
+            };
+            self.returned = pending == 0;
+            self.pending_returns = (pending > 0).then_some(pending);
+        }
 
-                if extended {
-                    addr = addr << 8 | self.machine.fetch() as u32;
-                }
+        if let Some(code) = self.machine.take_halt_request() {
+            self.halted = Some(HaltReason::Stop { code });
+        } else if self.machine.get_reg(&Register::PC) == pc {
+            self.halted = Some(HaltReason::SelfJump { address: pc });
+        } else if self.max_steps.is_some_and(|max| self.steps >= max) {
+            self.halted = Some(HaltReason::StepLimit { steps: self.steps });
+        }
+
+        // Stop before executing the instruction at a breakpoint
+        let pc = self.machine.get_reg(&Register::PC);
+        if self.breakpoints.hit(pc, &self.machine) {
+            self.breakpoint_hit = Some(pc);
+        }
+        if self.halted.is_some() || self.breakpoint_hit.is_some() || !self.watch_hits.is_empty() {
+            self.pending_returns = None;
+        }
+        Ok(())
+    }
+
+    /// Executes the instruction at PC. A JSUB is stepped over: running continues
+    /// until the subroutine returns with RSUB, then the runner is paused.
+    /// Returns whether a subroutine call is being stepped over.
+    pub fn step_over(&mut self) -> Result<bool, ExecError> {
+        let pc = self.machine.get_reg(&Register::PC);
+        let call = decode(&self.machine, pc).is_ok_and(|decoded| decoded.opcode() == Opcode::JSUB);
+        if call {
+            self.pending_returns = Some(0);
+        }
+        self.step()?;
+        Ok(self.pending_returns.is_some())
+    }
+
+    /// Keeps running until the current subroutine returns with RSUB, then pauses the runner.
+    /// Calls made on the way are tracked, so recursive subroutines stop at the right return.
+    pub fn step_out(&mut self) {
+        self.pending_returns = Some(1);
+    }
+
+    /// Whether running stops at a subroutine return, after [`Self::step_over`] or [`Self::step_out`].
+    pub fn returning(&self) -> bool {
+        self.pending_returns.is_some()
+    }
+
+    /// Stops waiting for a subroutine return.
+    pub fn cancel_return(&mut self) {
+        self.pending_returns = None;
+    }
+
+    /// Whether the last step returned from the subroutine that was stepped over or out of.
+    pub fn returned(&self) -> bool {
+        self.returned
+    }
+
+    /// Undoes the last executed instruction, returns false if there is no history left.
+    pub fn step_back(&mut self) -> bool {
+        let Some(record) = self.history.pop() else {
+            return false;
+        };
+
+        self.machine.undo(&record);
+        self.steps = record.steps;
+        self.halted = None;
+        self.breakpoint_hit = None;
+        self.watch_hits.clear();
+        self.pending_returns = None;
+        self.returned = false;
+        true
+    }
 
-                let instruct = Format34 { opcode, address: addr, flags };
-                Box::new(instruct)
+    /// Steps back until PC reaches a breakpoint or the history runs out.
+    /// Returns the number of instructions undone.
+    pub fn run_back(&mut self) -> usize {
+        let mut count = 0;
+        while self.step_back() {
+            count += 1;
+
+            let pc = self.machine.get_reg(&Register::PC);
+            if self.breakpoints.get(pc).is_some_and(|breakpoint| breakpoint.triggers(&self.machine)) {
+                self.breakpoint_hit = Some(pc);
+                break;
             }
-            _ => return Err(format!("Invalid opcode {opcode:02X} at address {:06X}.", self.machine.get_reg(&Register::PC) - 3)),
+        }
+        count
+    }
+
+    /// Replaces the device at the given address, also after loading a program.
+    pub fn attach_device(&mut self, address: u8, device: Box<dyn Device>) {
+        self.machine.attach_device(address, device);
+    }
+
+    /// Attaches the configured devices and sets the directory of the device files.
+    pub fn apply_config(&mut self, config: &MachineConfig) -> Result<(), ConfigError> {
+        for (address, device) in config.open_devices()? {
+            self.machine.attach_device(address, device);
+        }
+        self.machine.set_device_directory(config.device_directory());
+        Ok(())
+    }
+
+    /// Memory-mapped text screen, at 0xB800 unless changed.
+    pub fn text_screen(&self) -> Option<&TextScreen> {
+        self.text_screen.as_ref()
+    }
+
+    pub fn set_text_screen(&mut self, screen: Option<TextScreen>) {
+        self.text_screen = screen;
+    }
+
+    /// Rows of the text screen, read from memory.
+    pub fn screen_lines(&self) -> Option<Vec<String>> {
+        self.text_screen.map(|screen| screen.lines(&self.machine))
+    }
+
+    /// Memory-mapped framebuffer, 64x48 pixels at 0xA000 unless changed.
+    pub fn framebuffer(&self) -> Option<&Framebuffer> {
+        self.framebuffer.as_ref()
+    }
+
+    pub fn set_framebuffer(&mut self, framebuffer: Option<Framebuffer>) {
+        self.framebuffer = framebuffer;
+    }
+
+    /// Current image of the framebuffer, read from memory.
+    pub fn frame(&self) -> Option<Frame> {
+        self.framebuffer.map(|framebuffer| framebuffer.frame(&self.machine))
+    }
+
+    /// Gets the undo history of executed instructions.
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    /// Sets how many instructions can be undone.
+    pub fn set_history_capacity(&mut self, capacity: usize) {
+        self.history.set_capacity(capacity);
+    }
+
+    /// Tracer that records executed instructions, if tracing.
+    pub fn tracer(&self) -> Option<&Tracer> {
+        self.tracer.as_ref()
+    }
+
+    /// Mutable access to the tracer, e.g. to flush it.
+    pub fn tracer_mut(&mut self) -> Option<&mut Tracer> {
+        self.tracer.as_mut()
+    }
+
+    /// Sets the tracer that records executed instructions, returning the previous one.
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) -> Option<Tracer> {
+        std::mem::replace(&mut self.tracer, tracer)
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    /// Starts or stops counting executed instructions. Counts restart when a program is loaded.
+    pub fn set_profiling(&mut self, enabled: bool) {
+        if enabled != self.profiler.is_some() {
+            self.profiler = enabled.then(Profiler::default);
+        }
+    }
+
+    /// Reports the `top` most executed addresses along with opcode, label and call counts.
+    pub fn profile_report(&self, top: usize) -> Option<ProfileReport> {
+        self.profiler.as_ref().map(|profiler| profiler.report(&self.machine, &self.symbols, top))
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Starts or stops tracking executed instructions and data accesses.
+    /// Coverage restarts when a program is loaded.
+    pub fn set_coverage(&mut self, enabled: bool) {
+        if enabled == self.coverage.is_some() {
+            return;
+        }
+        self.machine.set_access_log(enabled);
+        self.coverage = enabled.then(Coverage::default);
+        self.reset_coverage();
+    }
+
+    /// Exports the coverage in the lcov format, mapped to the `.asm` source lines.
+    pub fn coverage_lcov(&self) -> Option<String> {
+        let map = self.load_map.as_ref()?;
+        self.coverage.as_ref().map(|coverage| coverage.lcov(map, &self.machine))
+    }
+
+    /// Annotates the `.asm` sources with execution counts and data accesses.
+    pub fn coverage_listing(&self) -> Option<String> {
+        let map = self.load_map.as_ref()?;
+        self.coverage.as_ref().map(|coverage| coverage.listing(map))
+    }
+
+    /// Clears the coverage and takes the bytes of the loaded sections as the loaded program.
+    fn reset_coverage(&mut self) {
+        let Some(coverage) = &mut self.coverage else {
+            return;
         };
+        coverage.clear();
+        let sections = self.load_map.iter().flat_map(|map| &map.sections);
+        let loaded = sections
+            .flat_map(|section| self.machine.memory.range(section.address..section.address + section.length))
+            .map(|(address, _)| *address);
+        coverage.set_loaded(loaded);
+    }
 
-        self.last_ex = Some(Instant::now());
-        instruction.exec(&mut self.machine)
+    /// Starts a trace entry for the fetched instruction, before it is executed.
+    fn trace_entry(&self, pc: u32, decoded: &Decoded) -> TraceEntry {
+        let line = disassemble_with_symbols(&self.machine, pc, &self.symbols);
+        let effective = match decoded {
+            Decoded::Format34(f) => f.effective_address(&self.machine, self.machine.get_reg(&Register::PC)),
+            _ => None,
+        };
+        TraceEntry {
+            step: self.steps,
+            address: pc,
+            bytes: line.bytes,
+            text: line.text,
+            effective,
+            registers: Vec::new(),
+            f_register: None,
+            memory: Vec::new(),
+        }
+    }
+
+    /// Completes the watchpoint hits of the instruction at `pc` with the values after it.
+    fn collect_watch_hits(&mut self, pc: u32) {
+        for mut hit in self.machine.take_watch_hits() {
+            hit.pc = pc;
+            hit.new = self.machine.peek_value(hit.address, hit.width);
+
+            // Watchpoints on change only trigger if the value changed
+            if hit.access == Access::CHANGE && hit.old == hit.new {
+                continue;
+            }
+            if let Some(watchpoint) = self.machine.watchpoints_mut().get_mut(hit.watchpoint) {
+                watchpoint.hits += 1;
+            }
+            self.watch_hits.push(hit);
+        }
     }
-}
\ No newline at end of file
+
+    /// Checks whether the address is within any of the loaded sections.
+    fn in_program(&self, address: u32) -> bool {
+        self.section_at(address).is_some()
+    }
+
+    /// Decodes the instruction at PC and moves PC past it.
+    fn fetch(&mut self) -> Result<Decoded, MachineError> {
+        let pc = self.machine.get_reg(&Register::PC);
+        let instruction = decode(&self.machine, pc)?;
+        self.machine.set_reg(&Register::PC, pc.wrapping_add(instruction.length()));
+
+        Ok(instruction)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assembler::assemble;
+    use crate::machine::breakpoint::Breakpoint;
+
+    const PROGRAM: &str = "\
+P      START 0
+       LDA   #1
+       LDX   #0
+loop   ADD   #1
+       STA   VALUE
+       STCH  VALUE
+       TIX   #5
+       JLT   loop
+halt   J     halt
+VALUE  RESW  1
+       END
+";
+
+    fn runner() -> Runner {
+        let mut runner = Runner::new();
+        runner.load_file(assemble(PROGRAM).unwrap().to_string().as_bytes()).unwrap();
+        runner
+    }
+
+    #[test]
+    fn stepping_back_restores_the_start() {
+        let mut runner = runner();
+        let start = Snapshot::capture(runner.machine());
+
+        let mut steps = 0;
+        while runner.halted().is_none() {
+            runner.step().unwrap();
+            steps += 1;
+        }
+        assert_eq!(runner.machine().get_reg(&Register::A), 6);
+        assert_ne!(Snapshot::capture(runner.machine()), start);
+
+        for _ in 0..steps {
+            assert!(runner.step_back());
+        }
+        assert!(!runner.step_back());
+        assert_eq!(runner.steps(), 0);
+        assert_eq!(runner.halted(), None);
+        assert_eq!(Snapshot::capture(runner.machine()), start);
+    }
+
+    #[test]
+    fn running_back_stops_at_breakpoint() {
+        let mut runner = runner();
+        // STA VALUE, after LDA, LDX and ADD
+        let store = 9;
+        runner.breakpoints_mut().set(Breakpoint::new(store));
+        while runner.halted().is_none() {
+            runner.step().unwrap();
+        }
+
+        // J, JLT, TIX, STCH and STA of the last iteration
+        assert_eq!(runner.run_back(), 5);
+        assert_eq!(runner.machine().get_reg(&Register::PC), store);
+        assert_eq!(runner.breakpoint_hit(), Some(store));
+        assert_eq!(runner.machine().get_reg(&Register::X), 4);
+    }
+}
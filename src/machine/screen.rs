@@ -0,0 +1,101 @@
+use crate::machine::machine::Machine;
+
+///
+/// Text screen mapped to memory, one byte per character, row after row.
+/// Bytes that are not printable ASCII are shown as spaces.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextScreen {
+    pub base: u32,
+    pub columns: u32,
+    pub rows: u32,
+}
+
+impl Default for TextScreen {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_BASE)
+    }
+}
+
+impl TextScreen {
+    pub const DEFAULT_BASE: u32 = 0xB800;
+
+    /// Screen of 80x25 characters at the base address.
+    pub fn new(base: u32) -> Self {
+        Self { base, columns: 80, rows: 25 }
+    }
+
+    /// Number of bytes the screen occupies.
+    pub fn size(&self) -> u32 {
+        self.columns.saturating_mul(self.rows)
+    }
+
+    /// Checks whether the whole screen is within memory.
+    pub fn fits(&self) -> bool {
+        self.base.checked_add(self.size()).is_some_and(|end| end <= Machine::MAX_ADDRESS + 1)
+    }
+
+    pub fn contains(&self, address: u32) -> bool {
+        address.checked_sub(self.base).is_some_and(|offset| offset < self.size())
+    }
+
+    /// Gets the character at the column and row, starting at 0.
+    /// Cells outside of memory are blank.
+    pub fn char_at(&self, machine: &Machine, column: u32, row: u32) -> char {
+        let address = row
+            .checked_mul(self.columns)
+            .and_then(|offset| offset.checked_add(column))
+            .and_then(|offset| self.base.checked_add(offset));
+        let byte = address.and_then(|address| machine.peek_byte(address).ok()).unwrap_or_default();
+        Self::to_char(byte)
+    }
+
+    /// Rows of the screen, with trailing spaces.
+    pub fn lines(&self, machine: &Machine) -> Vec<String> {
+        let mut lines = vec![" ".repeat(self.columns as usize); self.rows as usize];
+        // Empty range for a screen beyond memory
+        let end = self.base.saturating_add(self.size()).min(Machine::MAX_ADDRESS + 1).max(self.base);
+        for (&address, &byte) in machine.memory.range(self.base..end) {
+            let offset = address - self.base;
+            let (row, column) = ((offset / self.columns) as usize, (offset % self.columns) as usize);
+            lines[row].replace_range(column..=column, &Self::to_char(byte).to_string());
+        }
+        lines
+    }
+
+    fn to_char(byte: u8) -> char {
+        if byte.is_ascii_graphic() { byte as char } else { ' ' }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_characters_row_after_row() {
+        let mut machine = Machine::new();
+        let screen = TextScreen::default();
+        machine.write_byte(screen.base + 80 + 2, b'A').unwrap();
+        assert_eq!(screen.char_at(&machine, 2, 1), 'A');
+        assert_eq!(&screen.lines(&machine)[1][..4], "  A ");
+    }
+
+    #[test]
+    fn screen_must_fit_in_memory() {
+        assert!(TextScreen::new(Machine::MAX_ADDRESS + 1 - 2000).fits());
+        assert!(!TextScreen::new(Machine::MAX_ADDRESS + 2 - 2000).fits());
+        assert!(!TextScreen::new(u32::MAX).fits());
+    }
+
+    #[test]
+    fn cells_outside_of_memory_are_blank() {
+        let machine = Machine::new();
+        for base in [Machine::MAX_ADDRESS, u32::MAX] {
+            let screen = TextScreen::new(base);
+            assert_eq!(screen.char_at(&machine, 79, 24), ' ');
+            assert!(screen.lines(&machine).iter().all(|line| line.trim().is_empty()));
+            assert!(screen.contains(base) && !screen.contains(base - 1));
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use crate::errors::SnapshotError;
+use crate::loader::loader::{LoadMap, LoadedSection};
+use crate::loader::symbols::SymbolTable;
+use crate::machine::float::SicFloat;
+use crate::machine::machine::Machine;
+use crate::machine::register::Register;
+use crate::machine::word::WORD_MASK;
+
+///
+/// Saved state of the machine and runner, restored with [`crate::machine::runner::Runner::restore`].
+///
+/// The text format has one record per line:
+/// ```text
+/// SICSNAP 1
+/// FREQ 1000000
+/// STEPS 42
+/// REG PC 000003
+/// F 000000000000
+/// DEV 05 0000000C
+/// SECT "PROG" 000000 000020
+/// LABEL LOOP 000003
+/// SYM PROG 000000
+/// ENTRY 000000
+/// MEM 000000 0100052900
+/// ```
+/// Section names are quoted, so that unnamed sections keep their place.
+/// Memory is stored as runs of written bytes, so unused memory takes no space.
+/// Only file devices have a position; the others are left out.
+/// Source maps of assembled sections are not saved.
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Snapshot {
+    pub registers: [u32; 10],
+    pub f_register: SicFloat,
+    pub memory: BTreeMap<u32, u8>,
+    /// Stream positions of the devices, by device number.
+    pub devices: BTreeMap<u8, u64>,
+    pub frequency: u64,
+    pub steps: u64,
+    pub load_map: Option<LoadMap>,
+}
+
+impl Snapshot {
+    /// File extension of snapshot files.
+    pub const EXTENSION: &'static str = "snap";
+    const HEADER: &'static str = "SICSNAP 1";
+    /// Bytes per `MEM` line.
+    const RUN_LENGTH: usize = 32;
+
+    /// Captures the registers, memory and device positions of the machine.
+    pub fn capture(machine: &Machine) -> Self {
+        let devices = machine
+            .devices
+            .iter()
+            .filter_map(|(&number, device)| device.position().map(|position| (number, position)))
+            .collect();
+
+        Self {
+            registers: machine.registers,
+            f_register: machine.f_register,
+            memory: machine.memory.clone(),
+            devices,
+            ..Self::default()
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, SnapshotError> {
+        let mut lines = text.lines().enumerate().map(|(index, line)| (index + 1, line));
+        match lines.next() {
+            Some((_, header)) if header.trim() == Self::HEADER => {}
+            _ => return Err(format_error(1, "Not a snapshot file")),
+        }
+
+        let mut snapshot = Self::default();
+        let mut load_map: Option<LoadMap> = None;
+        for (line, text) in lines {
+            let fields = text.split_whitespace().collect::<Vec<_>>();
+            let Some((&record, fields)) = fields.split_first() else {
+                continue;
+            };
+            let field = |index: usize| {
+                fields.get(index).copied().ok_or(format_error(line, &format!("Missing field in {record} record")))
+            };
+            let hex = |index: usize| field(index).and_then(|value| parse_hex(line, value));
+            let address = |index: usize| {
+                let value = hex(index)?;
+                u32::try_from(value)
+                    .ok()
+                    .filter(|value| *value <= WORD_MASK)
+                    .ok_or(format_error(line, &format!("Value {value:X} is wider than 24 bits")))
+            };
+
+            match record {
+                "FREQ" => snapshot.frequency = parse_dec(line, field(0)?)?,
+                "STEPS" => snapshot.steps = parse_dec(line, field(0)?)?,
+                "REG" => {
+                    let name = field(0)?;
+                    let register = Register::from_name(name)
+                        .filter(|register| *register != Register::F)
+                        .ok_or(format_error(line, &format!("Unknown register {name}")))?;
+                    snapshot.registers[register.to_index()] = address(1)?;
+                }
+                "F" => snapshot.f_register = SicFloat::from_bits(hex(0)?),
+                "DEV" => {
+                    let number = u8::try_from(hex(0)?).map_err(|_| format_error(line, "Invalid device number"))?;
+                    snapshot.devices.insert(number, hex(1)?);
+                }
+                "SECT" => load_map.get_or_insert_with(LoadMap::default).sections.push(LoadedSection {
+                    name: field(0)?.trim_matches('"').to_string(),
+                    address: address(1)?,
+                    length: address(2)?,
+                    source: None,
+                    labels: SymbolTable::default(),
+                }),
+                "LABEL" => {
+                    let address = address(1)?;
+                    let section = load_map
+                        .as_mut()
+                        .and_then(|map| map.sections.last_mut())
+                        .ok_or(format_error(line, "LABEL record without section"))?;
+                    section.labels.insert(field(0)?, address);
+                }
+                "SYM" => {
+                    let address = address(1)?;
+                    load_map.get_or_insert_with(LoadMap::default).symbols.insert(field(0)?.to_string(), address);
+                }
+                "ENTRY" => load_map.get_or_insert_with(LoadMap::default).entry = address(0)?,
+                "MEM" => {
+                    let address = address(0)?;
+                    let bytes = field(1)?;
+                    if bytes.len() % 2 != 0 || !bytes.is_ascii() {
+                        return Err(format_error(line, "Invalid memory bytes"));
+                    }
+                    for (offset, i) in (0..bytes.len()).step_by(2).enumerate() {
+                        let byte = u8::from_str_radix(&bytes[i..i + 2], 16)
+                            .map_err(|_| format_error(line, "Invalid memory bytes"))?;
+                        let address = address + offset as u32;
+                        if address > Machine::MAX_ADDRESS {
+                            return Err(format_error(line, "Memory out of bounds"));
+                        }
+                        snapshot.memory.insert(address, byte);
+                    }
+                }
+                _ => return Err(format_error(line, &format!("Unknown record {record}"))),
+            }
+        }
+
+        snapshot.load_map = load_map;
+        Ok(snapshot)
+    }
+
+    /// Splits memory into runs of consecutive addresses, at most [`Self::RUN_LENGTH`] bytes long.
+    fn memory_runs(&self) -> Vec<(u32, Vec<u8>)> {
+        let mut runs: Vec<(u32, Vec<u8>)> = Vec::new();
+        for (&address, &byte) in &self.memory {
+            match runs.last_mut() {
+                Some((start, bytes))
+                    if *start + bytes.len() as u32 == address && bytes.len() < Self::RUN_LENGTH => bytes.push(byte),
+                _ => runs.push((address, vec![byte])),
+            }
+        }
+        runs
+    }
+}
+
+impl Display for Snapshot {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", Self::HEADER)?;
+        writeln!(f, "FREQ {}", self.frequency)?;
+        writeln!(f, "STEPS {}", self.steps)?;
+        for index in 0..10 {
+            match Register::from_index(index) {
+                Ok(register) if register != Register::F => {
+                    writeln!(f, "REG {} {:06X}", register.0, self.registers[register.to_index()])?
+                }
+                _ => {}
+            }
+        }
+        writeln!(f, "F {:012X}", self.f_register.to_bits())?;
+        for (number, position) in &self.devices {
+            writeln!(f, "DEV {number:02X} {position:08X}")?;
+        }
+        if let Some(map) = &self.load_map {
+            for section in &map.sections {
+                writeln!(f, "SECT \"{}\" {:06X} {:06X}", section.name, section.address, section.length)?;
+                for (name, address) in section.labels.iter() {
+                    writeln!(f, "LABEL {name} {address:06X}")?;
+                }
+            }
+            for (name, address) in &map.symbols {
+                writeln!(f, "SYM {name} {address:06X}")?;
+            }
+            writeln!(f, "ENTRY {:06X}", map.entry)?;
+        }
+        for (address, bytes) in self.memory_runs() {
+            let bytes = bytes.iter().map(|byte| format!("{byte:02X}")).collect::<String>();
+            writeln!(f, "MEM {address:06X} {bytes}")?;
+        }
+        Ok(())
+    }
+}
+
+fn format_error(line: usize, message: &str) -> SnapshotError {
+    SnapshotError::Format { line, message: message.to_string() }
+}
+
+fn parse_hex(line: usize, value: &str) -> Result<u64, SnapshotError> {
+    u64::from_str_radix(value, 16).map_err(|_| format_error(line, &format!("Invalid hex number '{value}'")))
+}
+
+fn parse_dec(line: usize, value: &str) -> Result<u64, SnapshotError> {
+    value.parse().map_err(|_| format_error(line, &format!("Invalid number '{value}'")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assembler::assemble;
+    use crate::loader::loader::load;
+
+    #[test]
+    fn round_trip_of_unnamed_program() {
+        let program = assemble("      START 100\n      LDA   #5\nhalt  J     halt\n      END\n").unwrap();
+        assert_eq!(program.name, "");
+        let mut machine = Machine::new();
+        let mut map = load(&mut machine, &[program], None).unwrap();
+        map.sections.iter_mut().for_each(|section| section.source = None);
+        let snapshot = Snapshot { steps: 3, frequency: 1000, load_map: Some(map), ..Snapshot::capture(&machine) };
+
+        let text = snapshot.to_string();
+        assert!(text.contains("SECT \"\" 000100 000006"), "{text}");
+        assert_eq!(Snapshot::parse(&text).unwrap(), snapshot);
+    }
+
+    #[test]
+    fn reads_unquoted_section_names() {
+        let snapshot = Snapshot::parse("SICSNAP 1\nSECT PROG 000000 000020\n").unwrap();
+        assert_eq!(snapshot.load_map.unwrap().sections[0].name, "PROG");
+    }
+
+    #[test]
+    fn rejects_values_wider_than_24_bits() {
+        for record in ["REG A 1000000", "SECT \"\" 1000000 000001", "SYM X 1000000", "ENTRY 1000000", "MEM 1000000 00"] {
+            let error = Snapshot::parse(&format!("SICSNAP 1\n{record}\n")).unwrap_err();
+            assert!(matches!(error, SnapshotError::Format { line: 2, .. }), "{record}: {error:?}");
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+use bitflags::bitflags;
+
+bitflags! {
+    ///
+    /// Fields of the SW register. Bit 0 of the SIC/XE specification is the leftmost bit of the word.
+    ///
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct StatusWord: u32 {
+        /// Supervisor mode (bit 0), user mode when clear.
+        const MODE = 1 << 23;
+        /// Idle state (bit 1), running when clear.
+        const IDLE = 1 << 22;
+        /// Process identifier (bits 2-5).
+        const ID = 0b1111 << 18;
+        /// Condition code (bits 6-7), equal when clear.
+        const CC = 0b11 << 16;
+        const CC_LT = 0b01 << 16;
+        const CC_GT = 0b10 << 16;
+        /// Interrupt mask (bits 8-11).
+        const MASK = 0b1111 << 12;
+        /// Interruption code (bits 16-23).
+        const ICODE = 0xFF;
+    }
+}
+
+impl StatusWord {
+    /// Gets the result of the last comparison.
+    pub fn cc(&self) -> Ordering {
+        if self.contains(Self::CC_LT) {
+            Ordering::Less
+        } else if self.contains(Self::CC_GT) {
+            Ordering::Greater
+        } else {
+            Ordering::Equal
+        }
+    }
+
+    /// Sets the condition code, leaving other fields as they are.
+    pub fn set_cc(&mut self, ordering: Ordering) {
+        self.remove(Self::CC);
+        match ordering {
+            Ordering::Less => self.insert(Self::CC_LT),
+            Ordering::Equal => {}
+            Ordering::Greater => self.insert(Self::CC_GT),
+        }
+    }
+
+    pub fn supervisor(&self) -> bool {
+        self.contains(Self::MODE)
+    }
+
+    pub fn idle(&self) -> bool {
+        self.contains(Self::IDLE)
+    }
+
+    pub fn id(&self) -> u8 {
+        ((self.bits() & Self::ID.bits()) >> 18) as u8
+    }
+
+    pub fn mask(&self) -> u8 {
+        ((self.bits() & Self::MASK.bits()) >> 12) as u8
+    }
+
+    pub fn icode(&self) -> u8 {
+        (self.bits() & Self::ICODE.bits()) as u8
+    }
+}
+
+impl Display for StatusWord {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let cc = match self.cc() {
+            Ordering::Less => '<',
+            Ordering::Equal => '=',
+            Ordering::Greater => '>',
+        };
+        write!(
+            f,
+            "CC {cc} {} {} ID {:X} MASK {:X} IC {:02X}",
+            if self.supervisor() { "SUP" } else { "USR" },
+            if self.idle() { "IDLE" } else { "RUN" },
+            self.id(),
+            self.mask(),
+            self.icode(),
+        )
+    }
+}
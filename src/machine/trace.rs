@@ -0,0 +1,239 @@
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use bitflags::bitflags;
+use crate::machine::float::SicFloat;
+use crate::machine::history::UndoRecord;
+use crate::machine::machine::Machine;
+use crate::machine::register::Register;
+
+bitflags! {
+    ///
+    /// Columns of a trace line. The instruction address is always written.
+    ///
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct TraceFields: u8 {
+        /// Number of instructions executed before
+        const STEP = 1 << 0;
+        /// Raw instruction bytes
+        const BYTES = 1 << 1;
+        const DISASSEMBLY = 1 << 2;
+        /// Operand address, after indexing and indirection
+        const EFFECTIVE = 1 << 3;
+        /// Changed registers, except PC
+        const REGISTERS = 1 << 4;
+        /// Written memory
+        const MEMORY = 1 << 5;
+    }
+}
+
+impl Default for TraceFields {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+///
+/// Consecutive bytes written by an instruction.
+/// Bytes that were never written before are 0 in `old`.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryWrite {
+    pub address: u32,
+    pub old: Vec<u8>,
+    pub new: Vec<u8>,
+}
+
+///
+/// Executed instruction and what it changed.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEntry {
+    pub step: u64,
+    pub address: u32,
+    pub bytes: Vec<u8>,
+    pub text: String,
+    pub effective: Option<u32>,
+    /// Changed registers with their old and new value.
+    pub registers: Vec<(&'static str, u32, u32)>,
+    pub f_register: Option<(SicFloat, SicFloat)>,
+    pub memory: Vec<MemoryWrite>,
+}
+
+impl TraceEntry {
+    /// Fills in the changes recorded while executing the instruction.
+    pub(crate) fn set_changes(&mut self, record: &UndoRecord, machine: &Machine) {
+        self.registers = record
+            .registers
+            .iter()
+            .filter_map(|&(index, old)| {
+                let register = Register::from_index(index as u8).ok()?;
+                (register != Register::PC).then(|| (register.0, old, machine.registers[index]))
+            })
+            .collect();
+        self.f_register = record.f_register.map(|old| (old, machine.f_register));
+
+        self.memory.clear();
+        for &(address, old) in &record.memory {
+            let old = old.unwrap_or_default();
+            let new = machine.peek_byte(address).unwrap_or_default();
+            match self.memory.last_mut() {
+                Some(run) if run.address.wrapping_add(run.new.len() as u32) == address => {
+                    run.old.push(old);
+                    run.new.push(new);
+                }
+                _ => self.memory.push(MemoryWrite { address, old: vec![old], new: vec![new] }),
+            }
+        }
+    }
+
+    /// Formats the entry as a single line with the given columns.
+    pub fn format(&self, fields: TraceFields) -> String {
+        let mut columns = Vec::new();
+        if fields.contains(TraceFields::STEP) {
+            columns.push(format!("{:>8}", self.step));
+        }
+        columns.push(format!("{:06X}", self.address));
+        if fields.contains(TraceFields::BYTES) {
+            columns.push(format!("{:<8}", hex(&self.bytes)));
+        }
+        if fields.contains(TraceFields::DISASSEMBLY) {
+            columns.push(format!("{:<20}", self.text));
+        }
+        if fields.contains(TraceFields::EFFECTIVE) {
+            let effective = self.effective.map_or("-".repeat(6), |address| format!("{address:06X}"));
+            columns.push(format!("EA={effective}"));
+        }
+        if fields.contains(TraceFields::REGISTERS) {
+            for (name, old, new) in &self.registers {
+                columns.push(format!("{name}={old:06X}->{new:06X}"));
+            }
+            if let Some((old, new)) = self.f_register {
+                columns.push(format!("F={:012X}->{:012X}", old.to_bits(), new.to_bits()));
+            }
+        }
+        if fields.contains(TraceFields::MEMORY) {
+            for write in &self.memory {
+                columns.push(format!("[{:06X}]={}->{}", write.address, hex(&write.old), hex(&write.new)));
+            }
+        }
+        columns.join("  ").trim_end().to_string()
+    }
+}
+
+impl Display for TraceEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.format(TraceFields::all()))
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02X}")).collect()
+}
+
+///
+/// Selects the traced instructions. Empty lists match everything.
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TraceFilter {
+    /// Address ranges, with the end exclusive.
+    pub ranges: Vec<(u32, u32)>,
+    pub opcodes: Vec<u8>,
+}
+
+impl TraceFilter {
+    pub fn matches(&self, address: u32, opcode: u8) -> bool {
+        (self.ranges.is_empty() || self.ranges.iter().any(|&(start, end)| (start..end).contains(&address)))
+            && (self.opcodes.is_empty() || self.opcodes.contains(&opcode))
+    }
+}
+
+enum TraceOutput {
+    Writer(Box<dyn Write>),
+    Buffer { entries: VecDeque<TraceEntry>, capacity: usize },
+}
+
+///
+/// Records executed instructions to a writer, one line each, or keeps the most recent ones in memory.
+///
+pub struct Tracer {
+    pub fields: TraceFields,
+    pub filter: TraceFilter,
+    output: TraceOutput,
+    /// First write error, writing stops after it.
+    error: Option<io::Error>,
+}
+
+impl Tracer {
+    pub fn writer(writer: impl Write + 'static) -> Self {
+        Self::with_output(TraceOutput::Writer(Box::new(writer)))
+    }
+
+    pub fn file(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::writer(BufWriter::new(File::create(path)?)))
+    }
+
+    /// Keeps the last `capacity` entries.
+    pub fn ring_buffer(capacity: usize) -> Self {
+        Self::with_output(TraceOutput::Buffer { entries: VecDeque::new(), capacity })
+    }
+
+    fn with_output(output: TraceOutput) -> Self {
+        Self { fields: TraceFields::default(), filter: TraceFilter::default(), output, error: None }
+    }
+
+    pub(crate) fn record(&mut self, entry: TraceEntry) {
+        match &mut self.output {
+            TraceOutput::Writer(writer) => {
+                if self.error.is_none() {
+                    if let Err(e) = writeln!(writer, "{}", entry.format(self.fields)) {
+                        self.error = Some(e);
+                    }
+                }
+            }
+            TraceOutput::Buffer { entries, capacity } => {
+                if *capacity == 0 {
+                    return;
+                }
+                if entries.len() >= *capacity {
+                    entries.pop_front();
+                }
+                entries.push_back(entry);
+            }
+        }
+    }
+
+    /// Entries in the ring buffer, oldest first. Empty when writing to a writer.
+    pub fn entries(&self) -> impl Iterator<Item = &TraceEntry> {
+        let entries = match &self.output {
+            TraceOutput::Buffer { entries, .. } => Some(entries.iter()),
+            TraceOutput::Writer(_) => None,
+        };
+        entries.into_iter().flatten()
+    }
+
+    /// Formats the entries in the ring buffer with the selected columns.
+    pub fn lines(&self) -> Vec<String> {
+        self.entries().map(|entry| entry.format(self.fields)).collect()
+    }
+
+    pub fn clear(&mut self) {
+        if let TraceOutput::Buffer { entries, .. } = &mut self.output {
+            entries.clear();
+        }
+    }
+
+    /// Flushes the writer, reporting the first error since tracing started.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        match &mut self.output {
+            TraceOutput::Writer(writer) => writer.flush(),
+            TraceOutput::Buffer { .. } => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use bitflags::bitflags;
+
+bitflags! {
+    ///
+    /// Memory accesses a watchpoint triggers on.
+    ///
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Access: u8 {
+        const READ = 1 << 0;
+        const WRITE = 1 << 1;
+        /// Write that changes the watched value.
+        const CHANGE = 1 << 2;
+    }
+}
+
+impl Display for Access {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let names = self.iter_names().map(|(name, _)| name.to_lowercase()).collect::<Vec<_>>();
+        f.write_str(&names.join("|"))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Width {
+    Byte,
+    Word,
+}
+
+impl Width {
+    pub fn bytes(self) -> u32 {
+        match self {
+            Width::Byte => 1,
+            Width::Word => 3,
+        }
+    }
+}
+
+///
+/// Watches `count` bytes or words starting at `address`.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watchpoint {
+    pub address: u32,
+    pub count: u32,
+    pub width: Width,
+    pub access: Access,
+    pub enabled: bool,
+    /// Number of times execution stopped at this watchpoint.
+    pub hits: u64,
+}
+
+impl Watchpoint {
+    pub fn new(address: u32, count: u32, width: Width, access: Access) -> Self {
+        Self { address, count: count.max(1), width, access, enabled: true, hits: 0 }
+    }
+
+    pub fn byte(address: u32, access: Access) -> Self {
+        Self::new(address, 1, Width::Byte, access)
+    }
+
+    pub fn word(address: u32, access: Access) -> Self {
+        Self::new(address, 1, Width::Word, access)
+    }
+
+    /// Gets the address of the watched byte or word containing the address.
+    pub fn unit(&self, address: u32) -> Option<u32> {
+        let offset = address.checked_sub(self.address)?;
+        let bytes = self.width.bytes();
+        if offset < self.count * bytes {
+            Some(self.address + offset / bytes * bytes)
+        } else {
+            None
+        }
+    }
+}
+
+///
+/// Access that triggered a watchpoint during an instruction.
+/// Old and new values are of the whole watched byte or word.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchHit {
+    /// Address of the watchpoint.
+    pub watchpoint: u32,
+    /// Address of the accessed byte or word.
+    pub address: u32,
+    pub width: Width,
+    pub access: Access,
+    /// Address of the instruction that made the access.
+    pub pc: u32,
+    pub old: u32,
+    pub new: u32,
+}
+
+impl Display for WatchHit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let digits = 2 * self.width.bytes() as usize;
+        write!(
+            f,
+            "Watchpoint {:06X}: {} of {:06X} by instruction at {:06X}, {:0digits$X} -> {:0digits$X}",
+            self.watchpoint, self.access, self.address, self.pc, self.old, self.new,
+        )
+    }
+}
+
+///
+/// Watchpoints by address.
+///
+#[derive(Debug, Default)]
+pub struct Watchpoints {
+    watchpoints: BTreeMap<u32, Watchpoint>,
+}
+
+impl Watchpoints {
+    /// Sets a watchpoint, replacing the one at the same address.
+    pub fn set(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.insert(watchpoint.address, watchpoint);
+    }
+
+    pub fn clear(&mut self, address: u32) -> Option<Watchpoint> {
+        self.watchpoints.remove(&address)
+    }
+
+    pub fn clear_all(&mut self) {
+        self.watchpoints.clear();
+    }
+
+    /// Sets the watchpoint, or clears the one at its address.
+    pub fn toggle(&mut self, watchpoint: Watchpoint) {
+        if self.clear(watchpoint.address).is_none() {
+            self.set(watchpoint);
+        }
+    }
+
+    pub fn get(&self, address: u32) -> Option<&Watchpoint> {
+        self.watchpoints.get(&address)
+    }
+
+    pub fn get_mut(&mut self, address: u32) -> Option<&mut Watchpoint> {
+        self.watchpoints.get_mut(&address)
+    }
+
+    /// Lists watchpoints ordered by address.
+    pub fn list(&self) -> impl Iterator<Item = &Watchpoint> {
+        self.watchpoints.values()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.watchpoints.is_empty()
+    }
+
+    /// Finds the enabled watchpoints containing the address.
+    pub(crate) fn matching(&self, address: u32, access: Access) -> impl Iterator<Item = &Watchpoint> {
+        self.watchpoints
+            .values()
+            .filter(move |w| w.enabled && w.access.intersects(access) && w.unit(address).is_some())
+    }
+}
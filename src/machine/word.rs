@@ -0,0 +1,15 @@
+/// Mask of a 24-bit word.
+pub const WORD_MASK: u32 = 0xFF_FFFF;
+
+/// Number of bits in a word.
+pub const WORD_BITS: u32 = 24;
+
+/// Interprets the 24-bit word as a two's complement signed value.
+pub fn to_signed(word: u32) -> i32 {
+    ((word << 8) as i32) >> 8
+}
+
+/// Converts the value to a 24-bit word, wrapping around on overflow.
+pub fn from_signed(value: i64) -> u32 {
+    value as u32 & WORD_MASK
+}
@@ -1,13 +1,25 @@
+use std::env;
 use std::io;
-use crate::ui::tui::TUI;
+use std::process::ExitCode;
+use simulator::ui::cli;
+use simulator::ui::tui::TUI;
 
-mod errors;
-mod device;
-mod machine;
-mod opcode;
-mod ui;
+fn main() -> ExitCode {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    if !args.is_empty() {
+        return cli::run(&args);
+    }
 
-fn main() -> io::Result<()> {
+    match run_tui() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run_tui() -> io::Result<()> {
     let mut tui = TUI::new()?;
     tui.init()?;
 
@@ -0,0 +1,120 @@
+use crate::errors::ObjectError;
+use crate::object::program::{Modification, ObjectProgram, TextRecord};
+
+/// Parses object programs. A file may hold several programs (control sections),
+/// each starting with an H record and ending with an E record.
+pub fn parse_object(source: &str) -> Result<Vec<ObjectProgram>, ObjectError> {
+    let mut programs = Vec::new();
+    let mut current: Option<ObjectProgram> = None;
+
+    for (ix, line) in source.lines().enumerate() {
+        let line_no = ix + 1;
+        let line = line.trim_end();
+        if line.is_empty() {
+            continue;
+        }
+        if !line.is_ascii() {
+            return Err(error(line_no, 1, "Non-ASCII characters in record."));
+        }
+
+        let record = &line[1..];
+        match (line.as_bytes()[0], current.as_mut()) {
+            (b'H', None) => {
+                let field = |from, to| hex_field(record, from, to, line_no);
+                if record.len() < 18 {
+                    return Err(error(line_no, 1, "H record too short."));
+                }
+                current = Some(ObjectProgram {
+                    name: record[..6].trim_end().to_string(),
+                    start: field(6, 12)?,
+                    length: field(12, 18)?,
+                    ..Default::default()
+                });
+            }
+            (b'H', Some(_)) => return Err(error(line_no, 1, "Expected E record before next H record.")),
+            (b'D', Some(program)) => {
+                if record.len() % 12 != 0 {
+                    return Err(error(line_no, 1, "Invalid D record length."));
+                }
+                for from in (0..record.len()).step_by(12) {
+                    let name = record[from..from + 6].trim_end().to_string();
+                    let address = hex_field(record, from + 6, from + 12, line_no)?;
+                    program.definitions.push((name, address));
+                }
+            }
+            (b'R', Some(program)) => {
+                for from in (0..record.len()).step_by(6) {
+                    let name = record[from..record.len().min(from + 6)].trim();
+                    if !name.is_empty() {
+                        program.references.push(name.to_string());
+                    }
+                }
+            }
+            (b'T', Some(program)) => {
+                let address = hex_field(record, 0, 6, line_no)?;
+                let length = hex_field(record, 6, 8, line_no)? as usize;
+                if record.len() != 8 + 2 * length {
+                    return Err(error(line_no, 8, "T record length does not match its contents."));
+                }
+                let bytes = (0..length)
+                    .map(|ix| hex_field(record, 8 + 2 * ix, 10 + 2 * ix, line_no).map(|b| b as u8))
+                    .collect::<Result<Vec<_>, _>>()?;
+                program.text.push(TextRecord { address, bytes });
+            }
+            (b'M', Some(program)) => {
+                let address = hex_field(record, 0, 6, line_no)?;
+                let half_bytes = hex_field(record, 6, 8, line_no)? as u8;
+                if !(1..=Modification::MAX_HALF_BYTES).contains(&half_bytes) {
+                    return Err(error(line_no, 8, "M record length must be between 1 and 6 half-bytes."));
+                }
+                let mut modification = Modification::relocation(address, half_bytes);
+
+                let symbol = &record[8..];
+                if let Some(name) = symbol.strip_prefix('+') {
+                    modification.symbol = Some(name.trim().to_string());
+                } else if let Some(name) = symbol.strip_prefix('-') {
+                    modification.symbol = Some(name.trim().to_string());
+                    modification.negative = true;
+                } else if !symbol.is_empty() {
+                    return Err(error(line_no, 10, "Expected '+' or '-' before symbol in M record."));
+                }
+                program.modifications.push(modification);
+            }
+            (b'E', Some(_)) => {
+                let mut program = current.take().unwrap();
+                if !record.is_empty() {
+                    program.entry = Some(hex_field(record, 0, 6, line_no)?);
+                }
+                programs.push(program);
+            }
+            (b'D' | b'R' | b'T' | b'M' | b'E', None) => {
+                return Err(error(line_no, 1, "Expected H record."));
+            }
+            (other, _) => return Err(error(line_no, 1, &format!("Unknown record type '{}'.", other as char))),
+        }
+    }
+
+    if current.is_some() {
+        return Err(error(source.lines().count(), 1, "Expected E record at the end of file."));
+    }
+    if programs.is_empty() {
+        return Err(error(1, 1, "No object program found."));
+    }
+
+    Ok(programs)
+}
+
+/// Parses the hexadecimal field between `from` and `to` of the record (without its type).
+fn hex_field(record: &str, from: usize, to: usize, line_no: usize) -> Result<u32, ObjectError> {
+    let column = from + 2;
+    let field = record
+        .get(from..to)
+        .ok_or(error(line_no, column, "Record too short."))?;
+
+    u32::from_str_radix(field, 16)
+        .map_err(|_| error(line_no, column, &format!("Invalid hex field '{field}'.")))
+}
+
+fn error(line: usize, column: usize, message: &str) -> ObjectError {
+    ObjectError { line, column, message: message.to_string() }
+}
@@ -0,0 +1,119 @@
+use std::fmt::{Display, Formatter};
+use crate::asm::source_map::SourceMap;
+use crate::loader::symbols::SymbolTable;
+
+/// Maximum number of bytes in a single T record.
+pub const MAX_TEXT_LEN: usize = 0x1E;
+
+///
+/// An object program (control section) as described by H, D, R, T, M and E records.
+///
+#[derive(Debug, Default)]
+pub struct ObjectProgram {
+    pub name: String,
+    pub start: u32,
+    pub length: u32,
+    pub entry: Option<u32>,
+    pub definitions: Vec<(String, u32)>,
+    pub references: Vec<String>,
+    pub text: Vec<TextRecord>,
+    pub modifications: Vec<Modification>,
+    /// Source statements, when the program was assembled here. Not part of the object file.
+    pub source: Option<SourceMap>,
+    /// Labels of the program, from the assembler or a symbol file. Not part of the object file.
+    pub labels: SymbolTable,
+}
+
+#[derive(Debug)]
+pub struct TextRecord {
+    pub address: u32,
+    pub bytes: Vec<u8>,
+}
+
+///
+/// Modification record - the field at `address` which is `half_bytes` long
+/// must be relocated by the load address of the program.
+///
+#[derive(Debug)]
+pub struct Modification {
+    pub address: u32,
+    pub half_bytes: u8,
+    /// External symbol to add (or subtract, when negative) instead of the program address.
+    pub symbol: Option<String>,
+    pub negative: bool,
+}
+
+impl Modification {
+    /// Longest field that can be modified, the 20-bit address of a format 4 instruction
+    /// or a whole word.
+    pub const MAX_HALF_BYTES: u8 = 6;
+
+    /// Relocation by the load address of the program.
+    pub fn relocation(address: u32, half_bytes: u8) -> Self {
+        Self { address, half_bytes, symbol: None, negative: false }
+    }
+}
+
+impl ObjectProgram {
+    /// Gets the byte of the T records at the address.
+    pub fn byte_at(&self, address: u32) -> Option<u8> {
+        self.text
+            .iter()
+            .find(|record| (record.address..record.address + record.bytes.len() as u32).contains(&address))
+            .map(|record| record.bytes[(address - record.address) as usize])
+    }
+
+    /// Appends the byte at the given address, either to the last T record
+    /// or to a new one if the address isn't contiguous or the record is full.
+    pub fn push_byte(&mut self, address: u32, byte: u8) {
+        match self.text.last_mut() {
+            Some(record) if record.address + record.bytes.len() as u32 == address
+                && record.bytes.len() < MAX_TEXT_LEN => record.bytes.push(byte),
+            _ => self.text.push(TextRecord { address, bytes: vec![byte] }),
+        }
+    }
+}
+
+impl Display for ObjectProgram {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name: String = self.name.chars().take(6).collect();
+        writeln!(f, "H{:<6}{:06X}{:06X}", name, self.start, self.length)?;
+
+        if !self.definitions.is_empty() {
+            write!(f, "D")?;
+            for (name, address) in &self.definitions {
+                write!(f, "{:<6}{:06X}", name, address)?;
+            }
+            writeln!(f)?;
+        }
+
+        if !self.references.is_empty() {
+            write!(f, "R")?;
+            for name in &self.references {
+                write!(f, "{:<6}", name)?;
+            }
+            writeln!(f)?;
+        }
+
+        for record in &self.text {
+            write!(f, "T{:06X}{:02X}", record.address, record.bytes.len())?;
+            for byte in &record.bytes {
+                write!(f, "{:02X}", byte)?;
+            }
+            writeln!(f)?;
+        }
+
+        for modification in &self.modifications {
+            write!(f, "M{:06X}{:02X}", modification.address, modification.half_bytes)?;
+            if let Some(symbol) = &modification.symbol {
+                write!(f, "{}{}", if modification.negative { '-' } else { '+' }, symbol)?;
+            }
+            writeln!(f)?;
+        }
+
+        match self.entry {
+            Some(entry) => writeln!(f, "E{:06X}", entry),
+            None => writeln!(f, "E"),
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use crate::errors::MachineError;
+use crate::machine::machine::Machine;
+use crate::opcode::format::{Format1, Format2, Format34, Nixbpe};
+use crate::opcode::instruction::Instruction;
+use crate::opcode::opcode::Opcode;
+
+///
+/// Source of bytes to decode instructions from.
+///
+pub trait MemoryReader {
+    fn read(&self, address: u32) -> Result<u8, MachineError>;
+}
+
+/// Reads memory without triggering watchpoints.
+impl MemoryReader for Machine {
+    fn read(&self, address: u32) -> Result<u8, MachineError> {
+        self.peek_byte(address)
+    }
+}
+
+/// Addresses are indices into the slice.
+impl MemoryReader for [u8] {
+    fn read(&self, address: u32) -> Result<u8, MachineError> {
+        self.get(address as usize)
+            .copied()
+            .ok_or(MachineError::MemoryOutOfBounds { address })
+    }
+}
+
+///
+/// Instruction decoded from memory, in one of the instruction formats.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Decoded {
+    Format1(Format1),
+    Format2(Format2),
+    Format34(Format34),
+}
+
+impl Decoded {
+    /// Length of the instruction in bytes.
+    pub fn length(&self) -> u32 {
+        self.format() as u32
+    }
+
+    /// Instruction format, 1 to 4.
+    pub fn format(&self) -> u8 {
+        match self {
+            Decoded::Format1(_) => 1,
+            Decoded::Format2(_) => 2,
+            Decoded::Format34(f) => if f.extended() { 4 } else { 3 },
+        }
+    }
+
+    pub fn opcode(&self) -> u8 {
+        match self {
+            Decoded::Format1(f) => f.opcode,
+            Decoded::Format2(f) => f.opcode,
+            Decoded::Format34(f) => f.opcode,
+        }
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        // Decoding only succeeds for opcodes in the table
+        Opcode::mnemonic(self.opcode()).unwrap_or_default()
+    }
+
+    /// Register operands of format 2 instructions, as raw nibbles.
+    pub fn registers(&self) -> Option<(u8, u8)> {
+        match self {
+            Decoded::Format2(f) => Some((f.r1, f.r2)),
+            _ => None,
+        }
+    }
+
+    /// Addressing flags of format 3 and 4 instructions.
+    pub fn nixbpe(&self) -> Option<Nixbpe> {
+        match self {
+            Decoded::Format34(f) => Some(f.nixbpe()),
+            _ => None,
+        }
+    }
+
+    /// Displacement (format 3) or address (format 4) field, as encoded.
+    pub fn displacement(&self) -> Option<u32> {
+        match self {
+            Decoded::Format34(f) => Some(f.address),
+            _ => None,
+        }
+    }
+}
+
+impl Instruction for Decoded {
+    fn exec(&self, machine: &mut Machine) -> Result<(), MachineError> {
+        match self {
+            Decoded::Format1(f) => f.exec(machine),
+            Decoded::Format2(f) => f.exec(machine),
+            Decoded::Format34(f) => f.exec(machine),
+        }
+    }
+}
+
+/// Decodes the instruction at the address, without side effects.
+pub fn decode<R: MemoryReader + ?Sized>(reader: &R, address: u32) -> Result<Decoded, MachineError> {
+    let byte = |offset: u32| reader.read(address.wrapping_add(offset));
+    let first_byte = byte(0)?;
+
+    // Formats 1 and 2 use the whole byte as opcode, formats 3 and 4 keep n and i in the lowest bits
+    let instruction = match Opcode::lookup(first_byte) {
+        Some((_, 1)) => Decoded::Format1(Format1 { opcode: first_byte }),
+        Some((_, 2)) => {
+            let registers = byte(1)?;
+
+            let r1 = (registers >> 4) & 0xF;
+            let r2 = registers & 0xF;
+
+            Decoded::Format2(Format2 { opcode: first_byte, r1, r2 })
+        }
+        _ => {
+            let opcode = first_byte & 0xFC;
+            if !matches!(Opcode::lookup(opcode), Some((_, 3))) {
+                return Err(MachineError::InvalidOpcode { opcode: first_byte });
+            }
+
+            let mut flags = byte(1)?;
+            let adplus = flags & 0xF;
+            flags >>= 4;
+
+            // Merge 2 bits from opcode
+            flags |= (first_byte & 0b11) << 4;
+            let extended = flags & 1 != 0;
+
+            let mut addr = (((adplus as u16) << 8) | byte(2)? as u16) as u32;
+
+            if extended {
+                addr = addr << 8 | byte(3)? as u32;
+            }
+
+            Decoded::Format34(Format34 { opcode, address: addr, flags })
+        }
+    };
+
+    Ok(instruction)
+}
+
+/// Decodes the instruction at the start of the bytes.
+pub fn decode_bytes(bytes: &[u8]) -> Result<Decoded, MachineError> {
+    decode(bytes, 0)
+}
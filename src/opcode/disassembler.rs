@@ -0,0 +1,162 @@
+use crate::loader::symbols::SymbolTable;
+use crate::machine::machine::Machine;
+use crate::machine::register::Register;
+use crate::opcode::decoder::{decode, Decoded};
+use crate::opcode::format::{Format2, Format34};
+use crate::opcode::opcode::Opcode;
+
+///
+/// Instruction at an address, in assembly syntax.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisassembledLine {
+    pub address: u32,
+    /// Symbol at the address
+    pub label: Option<String>,
+    pub bytes: Vec<u8>,
+    pub text: String,
+}
+
+/// Disassembles the instruction at the address. Bytes that are no instruction are shown as `BYTE`.
+/// Base-relative targets are computed with the current value of B.
+pub fn disassemble(machine: &Machine, address: u32) -> DisassembledLine {
+    disassemble_with_symbols(machine, address, &SymbolTable::default())
+}
+
+/// Disassembles the instruction at the address, showing target addresses with a symbol by name.
+pub fn disassemble_with_symbols(machine: &Machine, address: u32, symbols: &SymbolTable) -> DisassembledLine {
+    let decoded = decode(machine, address).ok();
+    let text = decoded.as_ref().map(|decoded| {
+        let mnemonic = decoded.mnemonic();
+        let next = address.wrapping_add(decoded.length());
+        match decoded {
+            Decoded::Format1(_) => mnemonic.to_string(),
+            Decoded::Format2(f) => format!("{mnemonic} {}", format2_operands(f)),
+            Decoded::Format34(f) => format34(mnemonic, f, next, machine.get_reg(&Register::B), symbols),
+        }
+    });
+
+    let length = decoded.map_or(1, |decoded| decoded.length());
+    let bytes = (0..length)
+        .map(|i| machine.peek_byte(address.wrapping_add(i)).unwrap_or_default())
+        .collect::<Vec<_>>();
+    let text = text.unwrap_or_else(|| format!("BYTE X'{:02X}'", bytes[0]));
+
+    let label = symbols.name(address).map(str::to_string);
+    DisassembledLine { address, label, bytes, text }
+}
+
+/// Computes the address the instruction at `address` would access, with the current X and B.
+/// Only format 3 and 4 instructions that are not immediate have one.
+pub fn effective_address(machine: &Machine, address: u32) -> Option<u32> {
+    let decoded = decode(machine, address).ok()?;
+    let next = address.wrapping_add(decoded.length());
+    match decoded {
+        Decoded::Format34(f) => f.effective_address(machine, next),
+        _ => None,
+    }
+}
+
+/// Disassembles `before` instructions before `pc` and `after` instructions from `pc` on.
+/// Decoding starts at `start`, so that the instructions before `pc` line up with it.
+pub fn disassemble_around(machine: &Machine, symbols: &SymbolTable, start: u32, pc: u32, before: usize, after: usize) -> Vec<DisassembledLine> {
+    let mut lines = Vec::new();
+    let mut address = start.min(pc);
+    while address < pc {
+        let line = disassemble_with_symbols(machine, address, symbols);
+        address = address.wrapping_add(line.bytes.len() as u32);
+        lines.push(line);
+    }
+    if address != pc {
+        // Instructions overlap PC, so decoding from start went wrong
+        lines.clear();
+    }
+    lines.drain(..lines.len().saturating_sub(before));
+
+    let mut address = pc;
+    for _ in 0..after {
+        let line = disassemble_with_symbols(machine, address, symbols);
+        address = address.wrapping_add(line.bytes.len() as u32);
+        lines.push(line);
+    }
+    lines
+}
+
+fn register_name(index: u8) -> String {
+    Register::from_index(index)
+        .map(|register| register.0.to_string())
+        .unwrap_or_else(|_| index.to_string())
+}
+
+fn format2_operands(f: &Format2) -> String {
+    match f.opcode {
+        Opcode::CLEAR | Opcode::TIXR => register_name(f.r1),
+        Opcode::SVC => f.r1.to_string(),
+        Opcode::SHIFTL | Opcode::SHIFTR => format!("{},{}", register_name(f.r1), f.r2 + 1),
+        _ => format!("{},{}", register_name(f.r1), register_name(f.r2)),
+    }
+}
+
+fn format34(mnemonic: &str, f: &Format34, next: u32, base: u32, symbols: &SymbolTable) -> String {
+    let prefix = if f.extended() { "+" } else { "" };
+    if f.opcode == Opcode::RSUB {
+        return format!("{prefix}{mnemonic}");
+    }
+
+    let target = f.target(next, base);
+    let operand = if f.immediate() && !f.pc_relative() && !f.base_relative() {
+        // Plain immediate values are numbers rather than addresses
+        target.to_string()
+    } else if let Some(name) = symbols.name(target) {
+        name.to_string()
+    } else {
+        format!("{target:06X}")
+    };
+    let mode = if f.immediate() {
+        "#"
+    } else if f.indirect() {
+        "@"
+    } else {
+        ""
+    };
+    let index = if f.indexed() { ",X" } else { "" };
+
+    format!("{prefix}{mnemonic} {mode}{operand}{index}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn machine_with(address: u32, bytes: &[u8]) -> Machine {
+        let mut machine = Machine::new();
+        for (offset, byte) in bytes.iter().enumerate() {
+            machine.write_byte(address + offset as u32, *byte).unwrap();
+        }
+        machine
+    }
+
+    #[test]
+    fn effective_address_uses_current_index() {
+        // LDA 10,X relative to PC
+        let mut machine = machine_with(0x100, &[0x03, 0xA0, 0x10]);
+        machine.set_reg(&Register::X, 3);
+        assert_eq!(effective_address(&machine, 0x100), Some(0x116));
+    }
+
+    #[test]
+    fn effective_address_follows_indirection() {
+        // J @20 relative to PC, the pointer holds 000555
+        let mut machine = machine_with(0x100, &[0x3E, 0x20, 0x20]);
+        machine.write_word(0x123, 0x555).unwrap();
+        assert_eq!(effective_address(&machine, 0x100), Some(0x555));
+    }
+
+    #[test]
+    fn immediate_operands_have_no_effective_address() {
+        // LDA #5 and RMO A, X
+        let machine = machine_with(0x100, &[0x01, 0x00, 0x05, 0xAC, 0x01]);
+        assert_eq!(effective_address(&machine, 0x100), None);
+        assert_eq!(effective_address(&machine, 0x103), None);
+    }
+}
@@ -1,17 +1,113 @@
+use bitflags::bitflags;
+use crate::machine::machine::Machine;
 use crate::machine::register::Register;
+use crate::machine::watchpoint::Width;
+use crate::machine::word::WORD_MASK;
+use crate::opcode::opcode::Opcode;
 
+bitflags! {
+    ///
+    /// Addressing flags of format 3 and 4 instructions.
+    ///
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Nixbpe: u8 {
+        /// Indirect, with i clear
+        const N = 1 << 5;
+        /// Immediate, with n clear
+        const I = 1 << 4;
+        /// Indexed
+        const X = 1 << 3;
+        /// Base-relative
+        const B = 1 << 2;
+        /// PC-relative
+        const P = 1 << 1;
+        /// Extended (format 4)
+        const E = 1 << 0;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Format1 {
     pub opcode: u8,
 }
 
+/// Register operands are kept as raw nibbles, since SVC and shifts use them as numbers.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Format2 {
     pub opcode: u8,
-    pub r1: Register,
-    pub r2: Register,
+    pub r1: u8,
+    pub r2: u8,
 }
 
+/// Flags are the nixbpe bits, with e as the lowest bit.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Format34 {
     pub opcode: u8,
     pub address: u32,
     pub flags: u8,
 }
+
+impl Format34 {
+    pub fn nixbpe(&self) -> Nixbpe {
+        Nixbpe::from_bits_truncate(self.flags)
+    }
+
+    pub fn extended(&self) -> bool {
+        self.nixbpe().contains(Nixbpe::E)
+    }
+
+    pub fn pc_relative(&self) -> bool {
+        self.nixbpe().contains(Nixbpe::P)
+    }
+
+    pub fn base_relative(&self) -> bool {
+        self.nixbpe().contains(Nixbpe::B)
+    }
+
+    pub fn indexed(&self) -> bool {
+        self.nixbpe().contains(Nixbpe::X)
+    }
+
+    /// n = 0, i = 1
+    pub fn immediate(&self) -> bool {
+        self.nixbpe() & (Nixbpe::N | Nixbpe::I) == Nixbpe::I
+    }
+
+    /// n = 1, i = 0
+    pub fn indirect(&self) -> bool {
+        self.nixbpe() & (Nixbpe::N | Nixbpe::I) == Nixbpe::N
+    }
+
+    /// Computes the target address, before indexing. Addresses wrap around at 24 bits.
+    /// `pc` is the address of the next instruction.
+    pub fn target(&self, pc: u32, base: u32) -> u32 {
+        if self.base_relative() {
+            // Displacement is unsigned
+            base.wrapping_add(self.address) & WORD_MASK
+        } else if self.pc_relative() {
+            // Displacement is signed, 12 bits or 20 bits if extended
+            let bits = if self.extended() { 20 } else { 12 };
+            let displacement = ((self.address << (32 - bits)) as i32) >> (32 - bits);
+            pc.wrapping_add(displacement as u32) & WORD_MASK
+        } else {
+            self.address
+        }
+    }
+
+    /// Computes the address of the operand, after indexing and indirection, without side effects.
+    /// Immediate operands and RSUB have no address. `pc` is the address of the next instruction.
+    pub fn effective_address(&self, machine: &Machine, pc: u32) -> Option<u32> {
+        if self.immediate() || self.opcode == Opcode::RSUB {
+            return None;
+        }
+
+        let mut address = self.target(pc, machine.get_reg(&Register::B));
+        if self.indexed() {
+            address = address.wrapping_add(machine.get_reg(&Register::X));
+        }
+        if self.indirect() {
+            address = machine.peek_value(address, Width::Word);
+        }
+        Some(address)
+    }
+}
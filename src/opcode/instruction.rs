@@ -1,221 +1,203 @@
+use std::cmp::Ordering;
+use crate::errors::MachineError;
+use crate::machine::float::SicFloat;
 use crate::machine::machine::Machine;
 use crate::machine::register::Register;
+use crate::machine::word::{from_signed, to_signed, WORD_BITS};
 use crate::opcode::format::{Format1, Format2, Format34};
 use crate::opcode::opcode::Opcode;
 
 pub trait Instruction {
-    fn exec(&self, machine: &mut Machine) -> Result<(), String>;
+    fn exec(&self, machine: &mut Machine) -> Result<(), MachineError>;
 }
 
 impl Instruction for Format1 {
-    fn exec(&self, machine: &mut Machine) -> Result<(), String> {
+    fn exec(&self, machine: &mut Machine) -> Result<(), MachineError> {
         match self.opcode {
             Opcode::FLOAT => {
-                let a = machine.get_reg(&Register::A);
-                machine.set_f_reg(a as f32);
+                let a = to_signed(machine.get_reg(&Register::A));
+                machine.set_f_reg(SicFloat::from_f64(a as f64));
                 Ok(())
             }
             Opcode::FIX => {
-                let f = machine.get_f_reg();
-                machine.set_reg(&Register::A, f as u32);
+                // Truncate towards zero
+                let f = machine.get_f_reg().to_f64();
+                machine.set_reg(&Register::A, from_signed(f as i64));
                 Ok(())
             }
             Opcode::NORM => {
-                todo!("What does NORM do?")
-            }
-            Opcode::SIO => {
-                todo!("SIO not implemented yet.")
-            }
-            Opcode::HIO => {
-                todo!("HIO not implemented yet.")
-            }
-            Opcode::TIO => {
-                todo!("TIO not implemented yet.")
+                let f = machine.get_f_reg();
+                machine.set_f_reg(f.normalize());
+                Ok(())
             }
-            _ => Err(format!("Invalid F1 opcode {}.", self.opcode)),
+            // I/O channels are not simulated
+            Opcode::SIO => Err(MachineError::Unimplemented { mnemonic: "SIO" }),
+            Opcode::HIO => Err(MachineError::Unimplemented { mnemonic: "HIO" }),
+            Opcode::TIO => Err(MachineError::Unimplemented { mnemonic: "TIO" }),
+            _ => Err(MachineError::InvalidOpcode { opcode: self.opcode }),
         }
     }
 }
 
 
 impl Instruction for Format2 {
-    fn exec(&self, machine: &mut Machine) -> Result<(), String> {
-        let r1 = machine.get_reg(&self.r1);
-        let r2 = machine.get_reg(&self.r2);
+    fn exec(&self, machine: &mut Machine) -> Result<(), MachineError> {
+        let reg1 = || Register::from_index(self.r1);
+        let reg2 = || Register::from_index(self.r2);
 
         match self.opcode {
-            Opcode::ADDR => {
-                machine.set_reg(&self.r2, r1 + r2);
-                Ok(())
-            }
-            Opcode::SUBR => {
-                machine.set_reg(&self.r2, r2 - r1);
-                Ok(())
-            }
-            Opcode::MULR => {
-                machine.set_reg(&self.r2, r1 * r2);
-                Ok(())
-            }
-            Opcode::DIVR => {
-                machine.set_reg(&self.r2, r2 / r1);
+            Opcode::ADDR | Opcode::SUBR | Opcode::MULR | Opcode::DIVR => {
+                let (reg1, reg2) = (reg1()?, reg2()?);
+                let r1 = to_signed(machine.get_reg(&reg1)) as i64;
+                let r2 = to_signed(machine.get_reg(&reg2)) as i64;
+
+                let value = match self.opcode {
+                    Opcode::ADDR => r2 + r1,
+                    Opcode::SUBR => r2 - r1,
+                    Opcode::MULR => r2 * r1,
+                    _ => r2.checked_div(r1).ok_or(MachineError::DivisionByZero)?,
+                };
+                machine.set_reg(&reg2, from_signed(value));
                 Ok(())
             }
             Opcode::COMPR => {
+                let r1 = machine.get_reg(&reg1()?);
+                let r2 = machine.get_reg(&reg2()?);
                 machine.cmp_vals(r1, r2);
                 Ok(())
             }
             Opcode::SHIFTL => {
-                machine.set_reg(&self.r1, r1 << r2);
+                // Circular shift, bits leaving on the left enter on the right
+                let reg1 = reg1()?;
+                let r1 = machine.get_reg(&reg1);
+                let n = (self.r2 as u32 + 1) % WORD_BITS;
+                machine.set_reg(&reg1, r1 << n | r1 >> (WORD_BITS - n));
                 Ok(())
             }
             Opcode::SHIFTR => {
-                machine.set_reg(&self.r1, r1 >> r2);
+                // Arithmetic shift, vacated bits are filled with the sign bit
+                let reg1 = reg1()?;
+                let r1 = to_signed(machine.get_reg(&reg1));
+                let n = self.r2 as u32 + 1;
+                machine.set_reg(&reg1, from_signed((r1 >> n) as i64));
                 Ok(())
             }
             Opcode::RMO => {
                 // RMO
-                machine.set_reg(&self.r2, r1);
+                let r1 = machine.get_reg(&reg1()?);
+                machine.set_reg(&reg2()?, r1);
                 Ok(())
             }
             Opcode::SVC => {
-                todo!("SVC not implemented yet.")
+                // Supervisor call stops the program, with n as the exit code
+                machine.request_halt(self.r1);
+                Ok(())
             }
             Opcode::CLEAR => {
-                machine.set_reg(&self.r1, 0);
+                machine.set_reg(&reg1()?, 0);
                 Ok(())
             }
             Opcode::TIXR => {
+                let r1 = machine.get_reg(&reg1()?);
                 // Increment X
                 machine.inc_x();
                 // Compare X to r1
                 machine.cmp_reg(&Register::X, r1);
                 Ok(())
             }
-            _ => Err(format!("Invalid F2 opcode {}.", self.opcode)),
+            _ => Err(MachineError::InvalidOpcode { opcode: self.opcode }),
         }
     }
 }
 
 impl Instruction for Format34 {
-    fn exec(&self, machine: &mut Machine) -> Result<(), String> {
-        let extended = self.flags & 1 == 1;
-        let flags = self.flags >> 1;
-        let mut provided_addr = self.address;
-        let mut use_addr = if flags & 0b10 != 0 {
-            // Base-relative
-            let base = machine.get_reg(&Register::B);
-            // Check negative
-            let provided_addr = provided_addr as i8;
-            (base as i64 + i64::from(provided_addr)) as u32
-        } else if flags & 1 == 1 {
-            // PC-relative
-            let pc = machine.get_reg(&Register::PC);
-
-            if extended && provided_addr & 1 << 23 != 0 {
-                provided_addr = provided_addr | 0xFFF00000;
-            } else if provided_addr & 1 << 11 != 0 {
-                provided_addr = provided_addr | 0xFFFFF000;
-            }
-
-            // Check negative
-            // When LDA #stackptr -> we must allow 260, but with
-            let provided_addr = provided_addr as i64;
-
-            (pc as i64 + provided_addr) as u32
-        } else {
-            // Simple
-            provided_addr
-        };
+    fn exec(&self, machine: &mut Machine) -> Result<(), MachineError> {
+        let pc = machine.get_reg(&Register::PC);
+        let mut use_addr = self.target(pc, machine.get_reg(&Register::B));
 
+        if self.indexed() {
+            use_addr = use_addr.wrapping_add(machine.get_reg(&Register::X));
+        }
 
-        let flags = flags >> 3;
-        let opvalue = if flags & 1 == 1 && flags & 0b10 == 0 {
-            // n = 0, i = 1
-            // Immediate
-            use_addr
-        } else if flags & 0b10 != 0 && flags & 1 == 0 {
-            // Posredno
-            use_addr = machine.read_word(use_addr);
-            machine.read_word(use_addr)
+        let operand = if self.immediate() {
+            Operand { address: use_addr, immediate: true }
+        } else if self.indirect() {
+            Operand { address: machine.read_word(use_addr)?, immediate: false }
         } else {
             // Simple
-            machine.read_word(use_addr)
+            Operand { address: use_addr, immediate: false }
         };
+        let use_addr = operand.address;
 
 
         match self.opcode {
             Opcode::LDA => {
-                machine.set_reg(&Register::A, opvalue);
+                machine.set_reg(&Register::A, operand.word(machine)?);
                 Ok(())
             }
             Opcode::LDX => {
-                machine.set_reg(&Register::X, opvalue);
+                machine.set_reg(&Register::X, operand.word(machine)?);
                 Ok(())
             },
             Opcode::LDL => {
-                machine.set_reg(&Register::L, opvalue);
+                machine.set_reg(&Register::L, operand.word(machine)?);
                 Ok(())
             },
             Opcode::STA => {
                 let value = machine.get_reg(&Register::A);
-                machine.write_word(use_addr, value);
+                machine.write_word(use_addr, value)?;
                 Ok(())
             },
             Opcode::STX => {
                 let value = machine.get_reg(&Register::X);
-                machine.write_word(use_addr, value);
+                machine.write_word(use_addr, value)?;
                 Ok(())
             },
             Opcode::STL => {
                 let value = machine.get_reg(&Register::L);
-                machine.write_word(use_addr, value);
-                Ok(())
-            },
-            Opcode::ADD => {
-                let a = machine.get_reg(&Register::A);
-                machine.set_reg(&Register::A, a + opvalue);
+                machine.write_word(use_addr, value)?;
                 Ok(())
             },
-            Opcode::SUB => {
-                let a = machine.get_reg(&Register::A);
-                machine.set_reg(&Register::A, a - opvalue);
-                Ok(())
-            },
-            Opcode::MUL => {
-                let a = machine.get_reg(&Register::A);
-                machine.set_reg(&Register::A, a * opvalue);
-                Ok(())
-            },
-            Opcode::DIV => {
-                let a = machine.get_reg(&Register::A);
-                machine.set_reg(&Register::A, a / opvalue);
+            Opcode::ADD | Opcode::SUB | Opcode::MUL | Opcode::DIV => {
+                let a = to_signed(machine.get_reg(&Register::A)) as i64;
+                let operand = to_signed(operand.word(machine)?) as i64;
+
+                let value = match self.opcode {
+                    Opcode::ADD => a + operand,
+                    Opcode::SUB => a - operand,
+                    Opcode::MUL => a * operand,
+                    _ => a.checked_div(operand).ok_or(MachineError::DivisionByZero)?,
+                };
+                machine.set_reg(&Register::A, from_signed(value));
                 Ok(())
             },
             Opcode::COMP => {
-                machine.cmp_reg(&Register::A, opvalue);
+                let value = operand.word(machine)?;
+                machine.cmp_reg(&Register::A, value);
                 Ok(())
             },
             Opcode::TIX => {
+                let value = operand.word(machine)?;
                 // Increment X
                 machine.inc_x();
                 // Compare X to value
-                machine.cmp_reg(&Register::X, opvalue);
+                machine.cmp_reg(&Register::X, value);
                 Ok(())
             },
             Opcode::JEQ => {
-                if machine.get_reg(&Register::SW) == 0 {
+                if machine.get_cc() == Ordering::Equal {
                     machine.set_reg(&Register::PC, use_addr);
                 }
                 Ok(())
             },
             Opcode::JGT => {
-                if machine.get_reg(&Register::SW) == 0x01 {
+                if machine.get_cc() == Ordering::Greater {
                     machine.set_reg(&Register::PC, use_addr);
                 }
                 Ok(())
             },
             Opcode::JLT => {
-                if machine.get_reg(&Register::SW) == 0x10 {
+                if machine.get_cc() == Ordering::Less {
                     machine.set_reg(&Register::PC, use_addr);
                 }
                 Ok(())
@@ -226,12 +208,12 @@ impl Instruction for Format34 {
             },
             Opcode::AND => {
                 let a = machine.get_reg(&Register::A);
-                machine.set_reg(&Register::A, a & opvalue);
+                machine.set_reg(&Register::A, a & operand.word(machine)?);
                 Ok(())
             },
             Opcode::OR => {
                 let a = machine.get_reg(&Register::A);
-                machine.set_reg(&Register::A, a | opvalue);
+                machine.set_reg(&Register::A, a | operand.word(machine)?);
                 Ok(())
             },
             Opcode::JSUB => {
@@ -246,109 +228,232 @@ impl Instruction for Format34 {
                 Ok(())
             },
             Opcode::LDCH => {
-                machine.set_reg(&Register::A, opvalue);
+                // Only the rightmost byte of A is replaced
+                let byte = operand.byte(machine)?;
+                let a = machine.get_reg(&Register::A);
+                machine.set_reg(&Register::A, a & !0xFF | byte as u32);
                 Ok(())
             },
             Opcode::STCH => {
                 let value = machine.get_reg(&Register::A) as u8;
-                machine.write_byte(use_addr, value);
-                Ok(())
-            },
-            Opcode::ADDF => {
-                let f = machine.get_f_reg();
-                machine.set_f_reg(f + f32::from_bits(opvalue));
-                Ok(())
-            },
-            Opcode::SUBF => {
-                let f = machine.get_f_reg();
-                machine.set_f_reg(f - f32::from_bits(opvalue));
-                Ok(())
-            },
-            Opcode::MULF => {
-                let f = machine.get_f_reg();
-                machine.set_f_reg(f * f32::from_bits(opvalue));
+                machine.write_byte(use_addr, value)?;
                 Ok(())
             },
-            Opcode::DIVF => {
-                let f = machine.get_f_reg();
-                machine.set_f_reg(f / f32::from_bits(opvalue));
+            Opcode::ADDF | Opcode::SUBF | Opcode::MULF | Opcode::DIVF => {
+                let f = machine.get_f_reg().to_f64();
+                let operand = operand.float(machine)?.to_f64();
+
+                let value = match self.opcode {
+                    Opcode::ADDF => f + operand,
+                    Opcode::SUBF => f - operand,
+                    Opcode::MULF => f * operand,
+                    _ if operand == 0.0 => return Err(MachineError::DivisionByZero),
+                    _ => f / operand,
+                };
+                machine.set_f_reg(SicFloat::from_f64(value));
                 Ok(())
             },
             Opcode::LDB => {
-                machine.set_reg(&Register::B, opvalue);
+                machine.set_reg(&Register::B, operand.word(machine)?);
                 Ok(())
             },
             Opcode::LDS => {
-                machine.set_reg(&Register::S, opvalue);
+                machine.set_reg(&Register::S, operand.word(machine)?);
                 Ok(())
             },
             Opcode::LDF => {
-                machine.set_reg(&Register::F, opvalue);
+                machine.set_f_reg(operand.float(machine)?);
                 Ok(())
             },
             Opcode::LDT => {
-                machine.set_reg(&Register::T, opvalue);
+                machine.set_reg(&Register::T, operand.word(machine)?);
                 Ok(())
             },
             Opcode::STB => {
                 let value = machine.get_reg(&Register::B);
-                machine.write_word(use_addr, value);
+                machine.write_word(use_addr, value)?;
                 Ok(())
             },
             Opcode::STS => {
                 let value = machine.get_reg(&Register::S);
-                machine.write_word(use_addr, value);
+                machine.write_word(use_addr, value)?;
                 Ok(())
             },
             Opcode::STF => {
-                let value = machine.get_reg(&Register::F);
-                machine.write_word(use_addr, value);
+                let value = machine.get_f_reg();
+                machine.write_float(use_addr, value)?;
                 Ok(())
             },
             Opcode::STT => {
                 let value = machine.get_reg(&Register::T);
-                machine.write_word(use_addr, value);
+                machine.write_word(use_addr, value)?;
                 Ok(())
             },
             Opcode::COMPF => {
-                let f = machine.get_f_reg();
-                machine.cmp_vals(f.to_bits(), opvalue);
+                let f = machine.get_f_reg().to_f64();
+                let value = operand.float(machine)?.to_f64();
+                machine.set_cc(f.total_cmp(&value));
                 Ok(())
             },
             Opcode::LPS => {
-                todo!("LPS not implemented yet.")
+                Err(MachineError::Unimplemented { mnemonic: "LPS" })
             },
             Opcode::STI => {
-                todo!("STI not implemented yet.")
+                Err(MachineError::Unimplemented { mnemonic: "STI" })
             },
             Opcode::RD => {
-                // Use all bits in this case!
-                let addr = (self.flags as u32) << 6 | self.address;
-                let opvalue = machine.read_byte(addr);
-                let value = machine.get_device(opvalue).read();
+                let device = operand.byte(machine)?;
+                let value = machine.read_device(device)?;
                 machine.set_reg(&Register::A, value as u32);
                 Ok(())
             },
             Opcode::WD => {
-                let addr = (self.flags as u32) << 6 | self.address;
-                let opvalue = machine.read_byte(addr);
+                let device = operand.byte(machine)?;
                 let value = machine.get_reg(&Register::A) as u8;
-                machine.get_device(opvalue).write(value);
-                Ok(())
+                machine.write_device(device, value)
             },
             Opcode::TD => {
-                let result = machine.get_device(opvalue as u8).test();
-                todo!("Test device @{opvalue} result: {result}");
+                // CC is set to < if the device is ready, = otherwise
+                let device = operand.byte(machine)?;
+                let ready = machine.test_device(device)?;
+                machine.set_cc(if ready { Ordering::Less } else { Ordering::Equal });
+                Ok(())
             },
             Opcode::STSW => {
                 let value = machine.get_reg(&Register::SW);
-                machine.write_word(opvalue, value);
+                machine.write_word(use_addr, value)?;
                 Ok(())
             },
             Opcode::SSK => {
-                todo!("SSK not implemented yet.")
+                Err(MachineError::Unimplemented { mnemonic: "SSK" })
             },
-            _ => Err(format!("Invalid F3/F4 opcode {}.", self.opcode)),
+            _ => Err(MachineError::InvalidOpcode { opcode: self.opcode }),
+        }
+    }
+}
+
+///
+/// Target of a format 3/4 instruction. Immediate operands are the address itself,
+/// otherwise the value is read from memory, with the width the instruction needs.
+///
+struct Operand {
+    address: u32,
+    immediate: bool,
+}
+
+impl Operand {
+    fn word(&self, machine: &Machine) -> Result<u32, MachineError> {
+        if self.immediate {
+            Ok(self.address)
+        } else {
+            machine.read_word(self.address)
+        }
+    }
+
+    fn byte(&self, machine: &Machine) -> Result<u8, MachineError> {
+        if self.immediate {
+            Ok(self.address as u8)
+        } else {
+            machine.read_byte(self.address)
         }
     }
+
+    /// Immediate values are the upper 24 bits of the floating point value.
+    fn float(&self, machine: &Machine) -> Result<SicFloat, MachineError> {
+        if self.immediate {
+            Ok(SicFloat::from_bits((self.address as u64) << 24))
+        } else {
+            machine.read_float(self.address)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assembler::assemble;
+    use crate::errors::ExecError;
+    use crate::machine::runner::Runner;
+
+    /// Assembles the instructions into a program ending with `halt J halt` and runs it.
+    fn run(instructions: &str) -> Result<Runner, ExecError> {
+        let source = format!("P      START 0\n{instructions}\nhalt   J     halt\nMINUS  WORD  -5\nPLUS   WORD  5\n       END\n");
+        let mut runner = Runner::new();
+        runner.load_file(assemble(&source).unwrap().to_string().as_bytes()).unwrap();
+        while runner.halted().is_none() {
+            runner.step()?;
+        }
+        Ok(runner)
+    }
+
+    fn register(runner: &Runner, register: Register) -> u32 {
+        runner.machine().get_reg(&register)
+    }
+
+    #[test]
+    fn signed_words() {
+        assert_eq!(to_signed(0xFFFFFF), -1);
+        assert_eq!(to_signed(0x800000), -0x800000);
+        assert_eq!(to_signed(0x7FFFFF), 0x7FFFFF);
+        assert_eq!(from_signed(-1), 0xFFFFFF);
+        assert_eq!(from_signed(0x800000), 0x800000);
+        assert_eq!(from_signed(0x1000001), 1);
+    }
+
+    #[test]
+    fn sub_wraps_below_zero() {
+        let runner = run("       LDA   #3\n       SUB   #5").unwrap();
+        assert_eq!(register(&runner, Register::A), 0xFFFFFE);
+        let runner = run("       LDA   #3\n       LDS   #5\n       SUBR  S, A").unwrap();
+        assert_eq!(register(&runner, Register::A), 0xFFFFFE);
+    }
+
+    #[test]
+    fn arithmetic_is_signed() {
+        let runner = run("       LDA   MINUS\n       MUL   #3\n       DIV   #2").unwrap();
+        assert_eq!(to_signed(register(&runner, Register::A)), -7);
+        let runner = run("       LDA   MINUS\n       ADD   #7").unwrap();
+        assert_eq!(register(&runner, Register::A), 2);
+    }
+
+    #[test]
+    fn shiftl_is_circular() {
+        let runner = run("       LDA   MINUS\n       SHIFTL A, 4").unwrap();
+        // FFFFFB rotated by 4
+        assert_eq!(register(&runner, Register::A), 0xFFFFBF);
+        let runner = run("       +LDA  #X'80001'\n       SHIFTL A, 8").unwrap();
+        assert_eq!(register(&runner, Register::A), 0x000108);
+    }
+
+    #[test]
+    fn shiftr_keeps_the_sign() {
+        let runner = run("       LDA   MINUS\n       SHIFTR A, 1").unwrap();
+        assert_eq!(to_signed(register(&runner, Register::A)), -3);
+        let runner = run("       LDA   PLUS\n       SHIFTR A, 1").unwrap();
+        assert_eq!(register(&runner, Register::A), 2);
+    }
+
+    #[test]
+    fn division_by_zero_is_a_fault() {
+        let error = run("       LDA   #5\n       DIV   #0").err().unwrap();
+        assert_eq!(error.kind, MachineError::DivisionByZero);
+        assert_eq!(error.pc, 3);
+        let error = run("       LDA   #5\n       LDS   #0\n       DIVR  S, A").err().unwrap();
+        assert_eq!(error.kind, MachineError::DivisionByZero);
+    }
+
+    #[test]
+    fn comparisons_are_signed() {
+        let runner = run("       LDA   MINUS\n       COMP  #1").unwrap();
+        assert_eq!(runner.machine().get_cc(), Ordering::Less);
+        let runner = run("       LDA   #1\n       COMP  MINUS").unwrap();
+        assert_eq!(runner.machine().get_cc(), Ordering::Greater);
+
+        let runner = run("       LDA   MINUS\n       LDS   #1\n       COMPR A, S").unwrap();
+        assert_eq!(runner.machine().get_cc(), Ordering::Less);
+        let runner = run("       LDA   MINUS\n       LDS   MINUS\n       COMPR A, S").unwrap();
+        assert_eq!(runner.machine().get_cc(), Ordering::Equal);
+        let runner = run("       LDA   #1\n       LDS   MINUS\n       COMPR A, S").unwrap();
+        assert_eq!(runner.machine().get_cc(), Ordering::Greater);
+    }
 }
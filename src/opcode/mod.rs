@@ -1,3 +1,5 @@
+pub mod decoder;
+pub mod disassembler;
 pub mod format;
 pub mod instruction;
-pub mod opcode;
\ No newline at end of file
+pub mod opcode;
@@ -60,4 +60,88 @@ impl Opcode {
     pub const HIO: u8 = 0xF4;
     pub const TIO: u8 = 0xF8;
 
-}
\ No newline at end of file
+    /// Mnemonic, opcode and format of every instruction.
+    /// Format 3 instructions can also be used in the extended format 4.
+    pub const TABLE: [(&'static str, u8, u8); 59] = [
+        ("LDA", Self::LDA, 3),
+        ("LDX", Self::LDX, 3),
+        ("LDL", Self::LDL, 3),
+        ("STA", Self::STA, 3),
+        ("STX", Self::STX, 3),
+        ("STL", Self::STL, 3),
+        ("ADD", Self::ADD, 3),
+        ("SUB", Self::SUB, 3),
+        ("MUL", Self::MUL, 3),
+        ("DIV", Self::DIV, 3),
+        ("COMP", Self::COMP, 3),
+        ("TIX", Self::TIX, 3),
+        ("JEQ", Self::JEQ, 3),
+        ("JGT", Self::JGT, 3),
+        ("JLT", Self::JLT, 3),
+        ("J", Self::J, 3),
+        ("AND", Self::AND, 3),
+        ("OR", Self::OR, 3),
+        ("JSUB", Self::JSUB, 3),
+        ("RSUB", Self::RSUB, 3),
+        ("LDCH", Self::LDCH, 3),
+        ("STCH", Self::STCH, 3),
+        ("ADDF", Self::ADDF, 3),
+        ("SUBF", Self::SUBF, 3),
+        ("MULF", Self::MULF, 3),
+        ("DIVF", Self::DIVF, 3),
+        ("LDB", Self::LDB, 3),
+        ("LDS", Self::LDS, 3),
+        ("LDF", Self::LDF, 3),
+        ("LDT", Self::LDT, 3),
+        ("STB", Self::STB, 3),
+        ("STS", Self::STS, 3),
+        ("STF", Self::STF, 3),
+        ("STT", Self::STT, 3),
+        ("COMPF", Self::COMPF, 3),
+        ("ADDR", Self::ADDR, 2),
+        ("SUBR", Self::SUBR, 2),
+        ("MULR", Self::MULR, 2),
+        ("DIVR", Self::DIVR, 2),
+        ("COMPR", Self::COMPR, 2),
+        ("SHIFTL", Self::SHIFTL, 2),
+        ("SHIFTR", Self::SHIFTR, 2),
+        ("RMO", Self::RMO, 2),
+        ("SVC", Self::SVC, 2),
+        ("CLEAR", Self::CLEAR, 2),
+        ("TIXR", Self::TIXR, 2),
+        ("FLOAT", Self::FLOAT, 1),
+        ("FIX", Self::FIX, 1),
+        ("NORM", Self::NORM, 1),
+        ("LPS", Self::LPS, 3),
+        ("STI", Self::STI, 3),
+        ("RD", Self::RD, 3),
+        ("WD", Self::WD, 3),
+        ("TD", Self::TD, 3),
+        ("STSW", Self::STSW, 3),
+        ("SSK", Self::SSK, 3),
+        ("SIO", Self::SIO, 1),
+        ("HIO", Self::HIO, 1),
+        ("TIO", Self::TIO, 1),
+    ];
+
+    /// Looks up the opcode and format of the given mnemonic (case-insensitive).
+    pub fn from_mnemonic(mnemonic: &str) -> Option<(u8, u8)> {
+        Self::TABLE
+            .iter()
+            .find(|(name, _, _)| name.eq_ignore_ascii_case(mnemonic))
+            .map(|(_, opcode, format)| (*opcode, *format))
+    }
+
+    /// Gets the mnemonic of the given opcode.
+    pub fn mnemonic(opcode: u8) -> Option<&'static str> {
+        Self::lookup(opcode).map(|(name, _)| name)
+    }
+
+    /// Looks up the mnemonic and format of the given opcode.
+    pub fn lookup(opcode: u8) -> Option<(&'static str, u8)> {
+        Self::TABLE
+            .iter()
+            .find(|(_, op, _)| *op == opcode)
+            .map(|(name, _, format)| (*name, *format))
+    }
+}
@@ -0,0 +1,429 @@
+use std::fs;
+use std::io;
+use std::process::ExitCode;
+use std::thread;
+use std::time::Duration;
+use crate::device::console::{ConsoleBuffer, ConsoleDevice};
+use crate::machine::config::MachineConfig;
+use crate::machine::framebuffer::{Framebuffer, PixelFormat};
+use crate::machine::machine::Machine;
+use crate::machine::register::Register;
+use crate::machine::runner::{HaltReason, Runner};
+use crate::machine::screen::TextScreen;
+use crate::machine::trace::{TraceFields, TraceFilter, Tracer};
+use crate::opcode::opcode::Opcode;
+
+const USAGE: &str = "\
+Usage: simulator [run FILE... [OPTIONS]]
+
+Without arguments, the interactive interface is started.
+A single snapshot file (.snap) continues the saved program.
+
+Run options:
+  --config FILE     machine configuration with the device backends (default: machine.toml, if present)
+  --base HEX        load address of the first section
+  --symbols FILE    read symbols (NAME ADDRESS lines) for the profile report
+  --max-steps N     stop after N instructions
+  --freq HZ         execute HZ instructions per second (default: as fast as possible)
+  --registers       print registers when the program stops
+  --memory START:END
+                    print memory between START and END (hex, END exclusive)
+  --no-mirror       do not copy the output of devices 1 and 2 to stdout and stderr
+  --screen HEX      print the 80x25 text screen mapped at HEX when the program stops
+  --framebuffer HEX:WIDTHxHEIGHT:BITS
+                    framebuffer mapped at HEX, with 8 (palette) or 2 bits per pixel (default: A000:64x48:8)
+  --frame FILE      write the framebuffer as an image when the program stops (.png, otherwise PPM)
+  --frame-every N   also write a frame every N instructions, numbered by the step in the file name
+  --save FILE       save a snapshot when the program stops
+  --profile         print the most executed instructions, opcodes, labels and calls
+  --lcov FILE       write the coverage of the .asm sources in lcov format
+  --listing FILE    write the .asm sources annotated with execution counts and data accesses
+  --trace FILE      write one line per executed instruction to FILE (- for stdout)
+  --trace-range START:END
+                    trace only instructions between START and END (hex, END exclusive, repeatable)
+  --trace-opcode MNEMONIC
+                    trace only instructions with the mnemonic (repeatable)
+  --trace-fields LIST
+                    comma separated trace columns: step, bytes, disassembly, effective,
+                    registers, memory (default: all)
+
+Exit status: 0 when halted (jump to itself, SVC or end of program),
+1 on usage or load error, 2 on execution fault, 3 when the step limit was reached.";
+
+/// Number of addresses in the profile report.
+const PROFILE_TOP: usize = 20;
+
+///
+/// Options of the `run` command.
+///
+#[derive(Debug, Default)]
+pub struct RunOptions {
+    pub files: Vec<String>,
+    pub config: Option<String>,
+    pub base: Option<u32>,
+    pub symbols: Vec<String>,
+    pub max_steps: Option<u64>,
+    pub frequency: Option<u64>,
+    pub registers: bool,
+    pub memory: Option<(u32, u32)>,
+    pub no_mirror: bool,
+    pub screen: Option<u32>,
+    pub framebuffer: Option<Framebuffer>,
+    pub frame: Option<String>,
+    pub frame_every: Option<u64>,
+    pub save: Option<String>,
+    pub trace: Option<String>,
+    pub trace_filter: TraceFilter,
+    pub trace_fields: TraceFields,
+    pub profile: bool,
+    pub lcov: Option<String>,
+    pub listing: Option<String>,
+}
+
+impl RunOptions {
+    /// Parses the arguments following the `run` command.
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = Self::default();
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("Missing value for {arg}."));
+            match arg.as_str() {
+                "--config" => options.config = Some(value()?.clone()),
+                "--base" => options.base = Some(parse_address(value()?)?),
+                "--symbols" => options.symbols.push(value()?.clone()),
+                "--max-steps" => options.max_steps = Some(parse_dec(value()?)?),
+                "--freq" => options.frequency = Some(parse_dec(value()?)?),
+                "--registers" => options.registers = true,
+                "--profile" => options.profile = true,
+                "--lcov" => options.lcov = Some(value()?.clone()),
+                "--listing" => options.listing = Some(value()?.clone()),
+                "--memory" => {
+                    options.memory = Some(parse_range(value()?)?);
+                }
+                "--no-mirror" => options.no_mirror = true,
+                "--screen" => {
+                    let screen = TextScreen::new(parse_address(value()?)?);
+                    if !screen.fits() {
+                        return Err(format!("The text screen at {:X} does not fit in memory.", screen.base));
+                    }
+                    options.screen = Some(screen.base);
+                }
+                "--framebuffer" => options.framebuffer = Some(parse_framebuffer(value()?)?),
+                "--frame" => options.frame = Some(value()?.clone()),
+                "--frame-every" => options.frame_every = Some(parse_dec(value()?)?.max(1)),
+                "--save" => options.save = Some(value()?.clone()),
+                "--trace" => options.trace = Some(value()?.clone()),
+                "--trace-range" => options.trace_filter.ranges.push(parse_range(value()?)?),
+                "--trace-opcode" => {
+                    let mnemonic = value()?;
+                    let (opcode, _) = Opcode::from_mnemonic(mnemonic)
+                        .ok_or(format!("Unknown mnemonic '{mnemonic}'."))?;
+                    options.trace_filter.opcodes.push(opcode);
+                }
+                "--trace-fields" => {
+                    let list = value()?;
+                    options.trace_fields = list.split(',').try_fold(TraceFields::empty(), |fields, name| {
+                        TraceFields::from_name(&name.trim().to_ascii_uppercase())
+                            .map(|field| fields | field)
+                            .ok_or(format!("Unknown trace field '{name}'."))
+                    })?;
+                }
+                _ if arg.starts_with("--") => return Err(format!("Unknown option {arg}.")),
+                _ => options.files.push(arg.clone()),
+            }
+        }
+
+        if options.files.is_empty() {
+            return Err("No file to run.".to_string());
+        }
+        if options.frame_every.is_some() && options.frame.is_none() {
+            return Err("--frame-every needs --frame.".to_string());
+        }
+        Ok(options)
+    }
+}
+
+fn parse_range(range: &str) -> Result<(u32, u32), String> {
+    let (start, end) = range
+        .split_once(':')
+        .ok_or(format!("Invalid range '{range}'."))?;
+    let (start, end) = (parse_address(start)?, parse_hex(end)?);
+    if end > Machine::MAX_ADDRESS + 1 || end < start {
+        return Err(format!("Invalid range '{range}', END must be between START and {:X}.", Machine::MAX_ADDRESS + 1));
+    }
+    Ok((start, end))
+}
+
+fn parse_framebuffer(value: &str) -> Result<Framebuffer, String> {
+    let invalid = || format!("Invalid framebuffer '{value}', expected HEX:WIDTHxHEIGHT:BITS.");
+    let mut parts = value.split(':');
+    let (Some(base), Some(size), Some(bits), None) = (parts.next(), parts.next(), parts.next(), parts.next()) else {
+        return Err(invalid());
+    };
+    let (width, height) = size.split_once(['x', 'X']).ok_or_else(invalid)?;
+    let bits = parse_dec(bits)?;
+    let framebuffer = Framebuffer {
+        base: parse_address(base)?,
+        width: u32::try_from(parse_dec(width)?).map_err(|_| invalid())?,
+        height: u32::try_from(parse_dec(height)?).map_err(|_| invalid())?,
+        format: PixelFormat::from_bits(bits as u32).ok_or(format!("Unsupported pixel size {bits}, use 8 or 2."))?,
+    };
+    if framebuffer.width == 0 || framebuffer.height == 0 {
+        return Err(invalid());
+    }
+    if !framebuffer.fits() {
+        return Err(format!("The framebuffer '{value}' does not fit in memory."));
+    }
+    Ok(framebuffer)
+}
+
+/// Inserts the step before the extension, `frame.png` becomes `frame-000100.png`.
+fn frame_path(path: &str, step: u64) -> String {
+    match path.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() && !extension.contains('/') => format!("{stem}-{step:06}.{extension}"),
+        _ => format!("{path}-{step:06}"),
+    }
+}
+
+fn parse_hex(value: &str) -> Result<u32, String> {
+    u32::from_str_radix(value, 16).map_err(|_| format!("Invalid hex number '{value}'."))
+}
+
+/// Parses a hex address within the 24-bit memory.
+fn parse_address(value: &str) -> Result<u32, String> {
+    let address = parse_hex(value)?;
+    if address > Machine::MAX_ADDRESS {
+        return Err(format!("Address {value} is outside of memory, the highest is {:X}.", Machine::MAX_ADDRESS));
+    }
+    Ok(address)
+}
+
+fn parse_dec(value: &str) -> Result<u64, String> {
+    value.parse().map_err(|_| format!("Invalid number '{value}'."))
+}
+
+/// Runs the command given on the command line and returns the exit status.
+pub fn run(args: &[String]) -> ExitCode {
+    match args.first().map(String::as_str) {
+        Some("run") => match RunOptions::parse(&args[1..]) {
+            Ok(options) => run_headless(&options),
+            Err(message) => {
+                eprintln!("{message}\n\n{USAGE}");
+                ExitCode::from(1)
+            }
+        },
+        Some("help" | "--help" | "-h") => {
+            println!("{USAGE}");
+            ExitCode::SUCCESS
+        }
+        _ => {
+            eprintln!("{USAGE}");
+            ExitCode::from(1)
+        }
+    }
+}
+
+/// Loads the files and runs them without the interface until the program halts.
+pub fn run_headless(options: &RunOptions) -> ExitCode {
+    let mut runner = Runner::new();
+    // Devices 1 and 2 are captured, and copied to stdout and stderr unless turned off
+    let (output, errors) = (ConsoleBuffer::default(), ConsoleBuffer::default());
+    if options.no_mirror {
+        runner.attach_device(1, Box::new(ConsoleDevice::new(output)));
+        runner.attach_device(2, Box::new(ConsoleDevice::new(errors)));
+    } else {
+        runner.attach_device(1, Box::new(ConsoleDevice::mirrored(output, io::stdout())));
+        runner.attach_device(2, Box::new(ConsoleDevice::mirrored(errors, io::stderr())));
+    }
+    let config = match &options.config {
+        Some(path) => MachineConfig::load(path).map(Some),
+        None => MachineConfig::load_default(),
+    };
+    if let Err(e) = config.and_then(|config| config.map_or(Ok(()), |config| runner.apply_config(&config))) {
+        eprintln!("{e}");
+        return ExitCode::from(1);
+    }
+    if let Err(e) = runner.open_paths(&options.files, options.base) {
+        eprintln!("{e}");
+        return ExitCode::from(1);
+    }
+    if !runner.written_files().is_empty() {
+        let paths = runner.written_files().iter().map(|path| path.display().to_string()).collect::<Vec<_>>();
+        eprintln!("Wrote {}.", paths.join(", "));
+    }
+    for warning in runner.warnings() {
+        eprintln!("{warning}");
+    }
+
+    for path in &options.symbols {
+        if let Err(e) = runner.load_symbols(path) {
+            eprintln!("{path}: {e}");
+            return ExitCode::from(1);
+        }
+    }
+
+    if let Some(path) = &options.trace {
+        let tracer = if path == "-" {
+            Ok(Tracer::writer(io::stdout()))
+        } else {
+            Tracer::file(path)
+        };
+        match tracer {
+            Ok(mut tracer) => {
+                tracer.fields = options.trace_fields;
+                tracer.filter = options.trace_filter.clone();
+                runner.set_tracer(Some(tracer));
+            }
+            Err(e) => {
+                eprintln!("{path}: {e}");
+                return ExitCode::from(1);
+            }
+        }
+    }
+
+    runner.set_profiling(options.profile);
+    runner.set_coverage(options.lcov.is_some() || options.listing.is_some());
+    runner.set_max_steps(options.max_steps);
+    runner.set_text_screen(Some(TextScreen::new(options.screen.unwrap_or(TextScreen::DEFAULT_BASE))));
+    runner.set_framebuffer(Some(options.framebuffer.unwrap_or_default()));
+    let period = options.frequency.map(|frequency| Duration::from_secs_f64(1.0 / frequency.max(1) as f64));
+    let status = loop {
+        if let Some(period) = period {
+            thread::sleep(period);
+        }
+
+        if let Err(e) = runner.step() {
+            eprintln!("{e}");
+            break 2;
+        }
+
+        if let (Some(path), Some(every)) = (&options.frame, options.frame_every) {
+            if runner.steps().is_multiple_of(every) && runner.halted().is_none() {
+                if let Some(frame) = runner.frame() {
+                    let path = frame_path(path, runner.steps());
+                    if let Err(e) = frame.save(&path) {
+                        eprintln!("{path}: {e}");
+                        return ExitCode::from(1);
+                    }
+                }
+            }
+        }
+
+        if let Some(reason) = runner.halted() {
+            eprintln!("Halted after {} steps: {reason}.", runner.steps());
+            break match reason {
+                HaltReason::StepLimit { .. } => 3,
+                _ => 0,
+            };
+        }
+    };
+
+    if let Some(Err(e)) = runner.tracer_mut().map(Tracer::flush) {
+        eprintln!("Trace: {e}");
+    }
+    if options.registers {
+        print_registers(runner.machine());
+    }
+    if let Some((start, end)) = options.memory {
+        print_memory(runner.machine(), start, end);
+    }
+    if let (Some(_), Some(lines)) = (options.screen, runner.screen_lines()) {
+        print_screen(&lines);
+    }
+    if let Some(report) = runner.profile_report(PROFILE_TOP) {
+        print!("{report}");
+    }
+    let reports = [(&options.lcov, runner.coverage_lcov()), (&options.listing, runner.coverage_listing())];
+    for (path, report) in reports {
+        if let (Some(path), Some(report)) = (path, report) {
+            if let Err(e) = fs::write(path, report) {
+                eprintln!("{path}: {e}");
+                return ExitCode::from(1);
+            }
+        }
+    }
+    if let (Some(path), Some(frame)) = (&options.frame, runner.frame()) {
+        if let Err(e) = frame.save(path) {
+            eprintln!("{path}: {e}");
+            return ExitCode::from(1);
+        }
+    }
+    if let Some(path) = &options.save {
+        if let Err(e) = runner.save_snapshot(path) {
+            eprintln!("{e}");
+            return ExitCode::from(1);
+        }
+    }
+
+    ExitCode::from(status)
+}
+
+fn print_registers(machine: &Machine) {
+    for index in 0..10 {
+        if let Ok(register) = Register::from_index(index) {
+            if register.to_index() == Register::F.to_index() {
+                println!("{:<2} {:012X}", register.0, machine.get_f_reg().to_bits());
+            } else {
+                println!("{:<2} {:06X}", register.0, machine.get_reg(&register));
+            }
+        }
+    }
+    println!("   {}", machine.get_status());
+}
+
+/// Prints the rows of the text screen without trailing spaces and empty rows at the end.
+fn print_screen(lines: &[String]) {
+    let rows = lines.iter().rposition(|line| !line.trim_end().is_empty()).map_or(0, |last| last + 1);
+    for line in &lines[..rows] {
+        println!("{}", line.trim_end());
+    }
+}
+
+/// Prints memory in rows of 16 bytes, like the memory view.
+fn print_memory(machine: &Machine, start: u32, end: u32) {
+    let end = end.min(Machine::MAX_ADDRESS + 1);
+    for row in (start..end).step_by(16) {
+        let bytes = (row..end.min(row + 16))
+            .map(|address| format!("{:02X}", machine.peek_byte(address).unwrap_or_default()))
+            .collect::<Vec<_>>();
+        println!("{row:06X}  {}", bytes.join(" "));
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<RunOptions, String> {
+        RunOptions::parse(&args.split_whitespace().map(str::to_string).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn accepts_addresses_within_memory() {
+        let options = parse("prog.obj --base FFFFFF --memory 0:1000000 --screen B800").unwrap();
+        assert_eq!(options.base, Some(0xFFFFFF));
+        assert_eq!(options.memory, Some((0, 0x1000000)));
+        assert_eq!(options.screen, Some(0xB800));
+    }
+
+    #[test]
+    fn rejects_addresses_outside_of_memory() {
+        for args in [
+            "prog.obj --base FFFFFFFF",
+            "prog.obj --base 1000000",
+            "prog.obj --memory 1000000:1000001",
+            "prog.obj --memory 0:1000001",
+            "prog.obj --memory 20:10",
+            "prog.obj --trace-range 0:FFFFFFFF",
+            "prog.obj --screen FFFFFFFF",
+            "prog.obj --framebuffer 1000000:8x8:8",
+            "prog.obj --framebuffer A000:100000x100000:8",
+            "prog.obj --framebuffer FFFFFF:2x1:8",
+            "prog.obj --framebuffer A000:0x8:8",
+        ] {
+            assert!(parse(args).is_err(), "{args}");
+        }
+    }
+}
@@ -1 +1,2 @@
-pub mod tui;
\ No newline at end of file
+pub mod cli;
+pub mod tui;
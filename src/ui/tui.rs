@@ -1,8 +1,8 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
 use std::fmt::{Display, Formatter};
-use std::fs::File;
 use std::io;
 use std::io::{stdout, Stdout};
-use std::ops::Deref;
 use crossterm::{event, ExecutableCommand};
 use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
 use ratatui::backend::CrosstermBackend;
@@ -10,11 +10,45 @@ use ratatui::{CompletedFrame, Terminal};
 use crossterm::event::{KeyCode, KeyEventKind};
 use ratatui::layout::{Alignment, Constraint, Margin, Rect};
 use ratatui::prelude::{Color, Direction, Layout, Line, Span, Style, Stylize};
+use ratatui::symbols::Marker;
+use ratatui::widgets::canvas::{Canvas, Points};
 use ratatui::widgets::{Block, Borders, Paragraph, Scrollbar, ScrollbarOrientation, ScrollbarState, Tabs, Wrap};
-use ratatui::widgets::canvas::{Canvas, Rectangle};
+use crate::device::console::{ConsoleBuffer, ConsoleDevice};
+use crate::device::keyboard::{KeyQueue, KeyboardDevice};
+use crate::errors::{ExecError, SimulatorError};
+use crate::machine::config::{Backend, MachineConfig};
 use crate::machine::machine::Machine;
 use crate::machine::register::Register;
 use crate::machine::runner::Runner;
+use crate::machine::watchpoint::{Access, Watchpoint};
+use crate::opcode::disassembler::{disassemble_around, effective_address};
+
+///
+/// Output of a console device, scrolled up by a number of lines from the end.
+///
+struct ConsolePane {
+    /// Devices writing to the pane
+    devices: Vec<u8>,
+    buffer: ConsoleBuffer,
+    /// 0 follows the output
+    scroll: usize,
+    hex: bool,
+}
+
+impl ConsolePane {
+    fn new(devices: Vec<u8>, buffer: ConsoleBuffer) -> Self {
+        Self { devices, buffer, scroll: 0, hex: false }
+    }
+
+    fn name(&self) -> String {
+        let devices = self.devices.iter().map(|device| format!("{device:02X}")).collect::<Vec<_>>();
+        match devices.len() {
+            0 => "No device".to_string(),
+            1 => format!("Device {}", devices[0]),
+            _ => format!("Devices {}", devices.join(", ")),
+        }
+    }
+}
 
 #[derive(PartialEq)]
 pub enum Mode {
@@ -41,26 +75,109 @@ pub struct TUI {
     selected_tab: u8,
     vertical_scroll: u32,
     selected_file: Option<(String, u8)>,
-    load_result: Result<(), String>,
+    marked_files: Vec<String>,
+    load_address: String,
+    show_load_map: bool,
+    show_profile: bool,
+    /// Whether the display panel shows the framebuffer instead of the text screen
+    show_framebuffer: bool,
+    load_result: Result<(), SimulatorError>,
+    notice: Option<String>,
+    memory_cursor: u32,
+    /// Address or symbol being typed to move the cursor to
+    goto_input: Option<String>,
+    /// Keys for the keyboard device (device 0)
+    keys: KeyQueue,
+    /// Output of devices 1 and 2
+    consoles: [ConsolePane; 2],
+    /// Pane that is scrolled and switched to hex
+    console_focus: usize,
+    /// Whether typed keys go to the keyboard device
+    typing: bool,
 }
 
 
 impl TUI {
     const TABS: [&'static str; 4] = ["Load file", "Start", "Step", "Quit"];
+    const SNAPSHOT_FILE: &'static str = "snapshot.snap";
+    const PROFILE_TOP: usize = 10;
+    /// Bytes per line of the console panes in hex mode
+    const CONSOLE_HEX_WIDTH: usize = 8;
     pub fn new() -> io::Result<Self> {
         let terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
+        let mut runner = Runner::new();
+        runner.set_profiling(true);
+        let (keys, consoles, load_result) = Self::attach_devices(&mut runner);
 
         Ok(Self {
             mode: Mode::STEP,
-            runner: Runner::new(),
+            runner,
             terminal,
             selected_tab: 0,
             vertical_scroll: 0,
             selected_file: None,
-            load_result: Ok(()),
+            marked_files: Vec::new(),
+            load_address: String::new(),
+            show_load_map: false,
+            show_profile: false,
+            show_framebuffer: false,
+            load_result,
+            notice: None,
+            memory_cursor: 0,
+            goto_input: None,
+            keys,
+            consoles,
+            console_focus: 0,
+            typing: false,
         })
     }
+    /// Attaches the devices of `machine.toml`. The terminal belongs to the interface,
+    /// so standard streams are replaced by the keyboard and the console panes.
+    fn attach_devices(runner: &mut Runner) -> (KeyQueue, [ConsolePane; 2], Result<(), SimulatorError>) {
+        let (mut config, mut result) = match MachineConfig::load_default() {
+            Ok(config) => (config.unwrap_or_default(), Ok(())),
+            Err(e) => (MachineConfig::default(), Err(e)),
+        };
+
+        let mut streams = BTreeMap::from([(0, Backend::Stdin), (1, Backend::Stdout), (2, Backend::Stderr)]);
+        streams.retain(|device, _| !config.devices.contains_key(device));
+        config.devices.retain(|&device, backend| match backend {
+            Backend::Stdin | Backend::Stdout | Backend::Stderr => {
+                streams.insert(device, backend.clone());
+                false
+            }
+            _ => true,
+        });
+        if result.is_ok() {
+            result = runner.apply_config(&config);
+        }
+
+        let keys = KeyQueue::default();
+        let mut consoles = [(); 2].map(|_| ConsolePane::new(Vec::new(), ConsoleBuffer::default()));
+        for (device, stream) in streams {
+            let pane = match stream {
+                Backend::Stdin => {
+                    runner.attach_device(device, Box::new(KeyboardDevice::new(keys.clone())));
+                    continue;
+                }
+                Backend::Stdout => &mut consoles[0],
+                _ => &mut consoles[1],
+            };
+            runner.attach_device(device, Box::new(ConsoleDevice::new(pane.buffer.clone())));
+            pane.devices.push(device);
+        }
+        (keys, consoles, result.map_err(SimulatorError::from))
+    }
+
     pub fn init(&mut self) -> io::Result<()> {
+        // Restore the terminal before reporting a panic
+        let hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            let _ = disable_raw_mode();
+            let _ = stdout().execute(LeaveAlternateScreen);
+            hook(info);
+        }));
+
         stdout().execute(EnterAlternateScreen)?;
         enable_raw_mode()?;
         self.terminal.clear()
@@ -79,6 +196,16 @@ impl TUI {
                 break;
             }
 
+            // Stop running on a fault, even while typing or entering an address
+            if let Some(Err(e)) = &step {
+                self.mode = Mode::STEP;
+                self.typing = false;
+                self.load_result = Err(e.clone().into());
+            }
+            if self.mode == Mode::RUN && (self.runner.halted().is_some() || self.runner.paused()) {
+                self.mode = Mode::STEP;
+            }
+
             self.draw(step)?;
         }
 
@@ -91,21 +218,118 @@ impl TUI {
         disable_raw_mode()
     }
 
-    fn handle_events(&mut self, step: &mut Option<Result<(), String>>) -> io::Result<bool> {
+    fn handle_events(&mut self, step: &mut Option<Result<(), ExecError>>) -> io::Result<bool> {
         if event::poll(std::time::Duration::from_millis(16))? {
             if let event::Event::Key(key) = event::read()? {
                 if key.kind == KeyEventKind::Press {
+                    if let Some(input) = &mut self.goto_input {
+                        match key.code {
+                            KeyCode::Char(c) if !c.is_whitespace() => input.push(c),
+                            KeyCode::Backspace => { input.pop(); }
+                            KeyCode::Enter => {
+                                let input = self.goto_input.take().unwrap_or_default();
+                                match self.runner.resolve_address(&input) {
+                                    Some(address) => self.set_cursor(address),
+                                    None => self.notice = Some(format!("Unknown address '{input}'.")),
+                                }
+                            }
+                            KeyCode::Esc => self.goto_input = None,
+                            _ => {}
+                        }
+                        return Ok(false);
+                    }
+
+                    if self.typing {
+                        let byte = match key.code {
+                            KeyCode::Char(c) if c.is_ascii() => Some(c as u8),
+                            KeyCode::Enter => Some(b'\n'),
+                            KeyCode::Tab => Some(b'\t'),
+                            KeyCode::Backspace => Some(0x08),
+                            KeyCode::Esc => {
+                                self.typing = false;
+                                return Ok(false);
+                            }
+                            _ => None,
+                        };
+                        if let Some(byte) = byte {
+                            self.keys.push(byte);
+                            return Ok(false);
+                        }
+                    }
+
                     match key.code {
+                        KeyCode::Char(c) if self.mode == Mode::LoadFile && c.is_ascii_hexdigit() => {
+                            // Edit load address
+                            self.load_address.push(c.to_ascii_uppercase());
+                            self.load_address.truncate(6);
+                        }
+                        KeyCode::Backspace if self.mode == Mode::LoadFile => {
+                            self.load_address.pop();
+                        }
+                        KeyCode::Char(' ') if self.mode == Mode::LoadFile => {
+                            // Mark selected file for linking
+                            if let Some((file, _)) = &self.selected_file {
+                                match self.marked_files.iter().position(|f| f == file) {
+                                    Some(ix) => { self.marked_files.remove(ix); }
+                                    None => self.marked_files.push(file.clone()),
+                                }
+                            }
+                        }
+                        KeyCode::Esc if self.mode == Mode::LoadFile => self.mode = Mode::STEP,
                         KeyCode::Char('q') => return Ok(true),
-                        KeyCode::Char('s') => self.mode = Mode::STEP,
+                        KeyCode::Char('m') => self.show_load_map = !self.show_load_map,
+                        KeyCode::Char('p') => self.show_profile = !self.show_profile,
+                        KeyCode::Char('d') => self.show_framebuffer = !self.show_framebuffer,
+                        KeyCode::Char('s') => {
+                            self.mode = Mode::STEP;
+                            self.runner.cancel_return();
+                        }
                         KeyCode::F(8) => {
                             if self.mode == Mode::STEP {
                                 *step = Some(self.runner.try_step());
                             } else {
                                 self.mode = Mode::STEP;
+                                self.runner.cancel_return();
+                            }
+                        }
+                        KeyCode::F(9) => {
+                            self.mode = Mode::RUN;
+                            self.runner.cancel_return();
+                        }
+                        KeyCode::Char('n') if self.mode == Mode::STEP => {
+                            match self.runner.step_over() {
+                                Ok(true) => self.mode = Mode::RUN,
+                                Ok(false) => *step = Some(Ok(())),
+                                Err(e) => *step = Some(Err(e)),
+                            }
+                        }
+                        KeyCode::Char('f') if self.mode == Mode::STEP => {
+                            self.runner.step_out();
+                            self.mode = Mode::RUN;
+                        }
+                        KeyCode::F(7) => {
+                            self.mode = Mode::STEP;
+                            self.runner.step_back();
+                        }
+                        KeyCode::F(6) => {
+                            self.mode = Mode::STEP;
+                            self.runner.run_back();
+                        }
+                        KeyCode::F(2) => {
+                            self.notice = None;
+                            match self.runner.save_snapshot(Self::SNAPSHOT_FILE) {
+                                Ok(()) => self.notice = Some(format!("Snapshot saved to {}.", Self::SNAPSHOT_FILE)),
+                                Err(e) => self.load_result = Err(e.into()),
+                            }
+                        }
+                        KeyCode::F(3) => {
+                            self.mode = Mode::STEP;
+                            self.notice = None;
+                            self.load_result = self.runner.load_snapshot(Self::SNAPSHOT_FILE).map_err(SimulatorError::from);
+                            if self.load_result.is_ok() {
+                                self.notice = Some(format!("Snapshot restored from {}.", Self::SNAPSHOT_FILE));
                             }
                         }
-                        KeyCode::F(9) => self.mode = Mode::RUN,
                         KeyCode::Delete => self.runner.stop(),
                         KeyCode::Tab => {
                             self.selected_tab += 1;
@@ -124,10 +348,23 @@ impl TUI {
                             // Open tab
                             match self.selected_tab {
                                 0 => {
-                                    if self.mode == Mode::LoadFile && self.selected_file.is_some() {
-                                        // Load file
-                                        let file = File::open(&self.selected_file.as_ref().unwrap().0).unwrap();
-                                        self.load_result = self.runner.load_file(&file);
+                                    if let (Mode::LoadFile, Some((path, _))) = (&self.mode, &self.selected_file) {
+                                        // Load marked files, or just the selected one
+                                        let paths = if self.marked_files.is_empty() {
+                                            vec![path.clone()]
+                                        } else {
+                                            std::mem::take(&mut self.marked_files)
+                                        };
+                                        self.notice = None;
+                                        self.load_result = self.load_paths(&paths);
+                                        let mut notices = self.runner.warnings().to_vec();
+                                        if !self.runner.written_files().is_empty() {
+                                            let paths = self.runner.written_files().iter().map(|path| path.display().to_string());
+                                            notices.insert(0, format!("Wrote {}.", paths.collect::<Vec<_>>().join(", ")));
+                                        }
+                                        if !notices.is_empty() {
+                                            self.notice = Some(notices.join(" "));
+                                        }
                                         self.mode = Mode::STEP;
                                     } else {
                                         // Open file dialog
@@ -154,9 +391,7 @@ impl TUI {
                             };
                         }
                         KeyCode::Up => {
-                            if self.vertical_scroll > 0 {
-                                self.vertical_scroll -= 1;
-                            }
+                            self.vertical_scroll = self.vertical_scroll.saturating_sub(1);
                         }
                         KeyCode::Down => {
                             self.vertical_scroll += 1;
@@ -176,20 +411,49 @@ impl TUI {
                             // Open file
                             self.mode = Mode::LoadFile;
                         }
-                        KeyCode::Char('j') => {
+                        KeyCode::Char('j') if self.mode == Mode::LoadFile => {
                             if let Some((_selected, ix)) = &mut self.selected_file {
-                                if *ix < 0xFF {
-                                    *ix += 1;
-                                }
+                                *ix = ix.saturating_add(1);
                             }
                         }
-                        KeyCode::Char('k') => {
+                        KeyCode::Char('k') if self.mode == Mode::LoadFile => {
                             if let Some((_, ix)) = &mut self.selected_file {
                                 if *ix > 0 {
                                     *ix -= 1;
                                 }
                             }
                         }
+                        // Move memory cursor
+                        KeyCode::Char('h') => self.move_cursor(-1),
+                        KeyCode::Char('l') => self.move_cursor(1),
+                        KeyCode::Char('k') => self.move_cursor(-16),
+                        KeyCode::Char('j') => self.move_cursor(16),
+                        KeyCode::Char('b') => {
+                            self.runner.breakpoints_mut().toggle(self.memory_cursor);
+                        }
+                        KeyCode::Char('g') if self.mode != Mode::LoadFile => {
+                            self.notice = None;
+                            self.goto_input = Some(String::new());
+                        }
+                        KeyCode::Char('i') if self.mode != Mode::LoadFile => {
+                            self.notice = None;
+                            self.typing = true;
+                        }
+                        KeyCode::Char('c') => self.console_focus = (self.console_focus + 1) % self.consoles.len(),
+                        KeyCode::Char('[') => self.consoles[self.console_focus].scroll += 1,
+                        KeyCode::Char(']') => {
+                            let pane = &mut self.consoles[self.console_focus];
+                            pane.scroll = pane.scroll.saturating_sub(1);
+                        }
+                        KeyCode::Char('x') => {
+                            let pane = &mut self.consoles[self.console_focus];
+                            pane.hex = !pane.hex;
+                            pane.scroll = 0;
+                        }
+                        KeyCode::Char('w') => {
+                            let watchpoint = Watchpoint::word(self.memory_cursor, Access::WRITE);
+                            self.runner.watchpoints_mut().toggle(watchpoint);
+                        }
                         _ => {}
                     }
 
@@ -198,7 +462,34 @@ impl TUI {
         }
         Ok(false)
     }
-    fn draw(&mut self, step: Option<Result<(), String>>) -> io::Result<CompletedFrame> {
+    /// Moves the memory cursor by the offset, scrolling to keep it visible.
+    fn move_cursor(&mut self, offset: i64) {
+        let cursor = (self.memory_cursor as i64 + offset).clamp(0, Machine::MAX_ADDRESS as i64);
+        self.set_cursor(cursor as u32);
+    }
+
+    /// Moves the memory cursor to the address, scrolling to keep it visible.
+    fn set_cursor(&mut self, address: u32) {
+        self.memory_cursor = address.min(Machine::MAX_ADDRESS);
+
+        let row = self.memory_cursor >> 4;
+        if row < self.vertical_scroll {
+            self.vertical_scroll = row;
+        } else if row >= self.vertical_scroll + 16 {
+            self.vertical_scroll = row - 15;
+        }
+    }
+
+    /// Links and loads object files at the load address.
+    /// `.asm` source files are assembled first, a single `.snap` file is restored.
+    fn load_paths(&mut self, paths: &[String]) -> Result<(), SimulatorError> {
+        // Only hex digits can be typed in, so parsing fails just for the empty address
+        let base = u32::from_str_radix(&self.load_address, 16).ok();
+
+        self.runner.open_paths(paths, base)
+    }
+
+    fn draw(&mut self, step: Option<Result<(), ExecError>>) -> io::Result<CompletedFrame<'_>> {
         self.terminal.draw(|frame| {
             let area = frame.size();
 
@@ -232,7 +523,7 @@ impl TUI {
                 layout[0]);
 
             frame.render_widget(
-                Paragraph::new(format!("Help: press q to quit, F8 to step or F9 to run. Current mode: {}.", self.mode).as_str())
+                Paragraph::new(format!("Help: press q to quit, F8 to step or F9 to run, n to step over a JSUB or f to step out of a subroutine, F7 to step back or F6 to run back, F2 to save a snapshot or F3 to restore it, h/j/k/l to move the cursor, g to go to an address or symbol, b for breakpoint, w for watchpoint, m for load map, p for profile, d to switch between the text screen and the framebuffer, i to type to the program, c to switch console pane, [ and ] to scroll it, x for hex. Current mode: {}.", self.mode).as_str())
                     .wrap(Wrap { trim: true })
                     .block(Block::new().borders(Borders::ALL)),
                 layout[4]);
@@ -268,7 +559,12 @@ impl TUI {
                 let register = Register::from_index(i as u8);
 
                 if let Ok(register) = register {
-                    let par = Paragraph::new(format!("{}: {:08X}", register.0, self.runner.machine().get_reg(&register)))
+                    let value = if register.to_index() == Register::F.to_index() {
+                        format!("{:012X}", self.runner.machine().get_f_reg().to_bits())
+                    } else {
+                        format!("{:08X}", self.runner.machine().get_reg(&register))
+                    };
+                    let par = Paragraph::new(format!("{}: {}", register.0, value))
                         .alignment(Alignment::Center)
                         .on_black()
                         .green();
@@ -283,6 +579,51 @@ impl TUI {
                 }
             }
 
+            // Decode the status word fields
+            register_widgets.push(
+                Paragraph::new("Status Word")
+                    .cyan()
+                    .on_black()
+            );
+
+            let status = self.runner.machine().get_status();
+            let cc = match status.cc() {
+                Ordering::Less => "<",
+                Ordering::Equal => "=",
+                Ordering::Greater => ">",
+            };
+            let fields = [
+                format!("CC: {}  Mode: {}", cc, if status.supervisor() { "S" } else { "U" }),
+                format!("Idle: {}  ID: {:X}", if status.idle() { "Y" } else { "N" }, status.id()),
+                format!("Mask: {:X}  IC: {:02X}", status.mask(), status.icode()),
+            ];
+            for field in fields {
+                register_widgets.push(
+                    Paragraph::new(field)
+                        .alignment(Alignment::Center)
+                        .on_black()
+                        .green()
+                );
+            }
+
+            // Memory cursor, with the symbol it belongs to
+            register_widgets.push(
+                Paragraph::new("Cursor")
+                    .cyan()
+                    .on_black()
+            );
+            let symbols = self.runner.symbols();
+            let cursor = match symbols.nearest(self.memory_cursor) {
+                Some(_) => format!("{:06X} {}", self.memory_cursor, symbols.describe(self.memory_cursor)),
+                None => format!("{:06X}", self.memory_cursor),
+            };
+            register_widgets.push(
+                Paragraph::new(cursor)
+                    .alignment(Alignment::Center)
+                    .on_black()
+                    .green()
+            );
+
             let register_layout = Layout::default()
                 .direction(Direction::Vertical)
                 .constraints(register_widgets
@@ -300,12 +641,36 @@ impl TUI {
             }
 
 
-            let vertical_scroll = self.vertical_scroll as usize;
+            // Breakpoints and watchpoints
+            let breakpoints = self.runner
+                .breakpoints()
+                .list()
+                .map(|b| (b.enabled, format!("B {:06X} {} hits: {}", b.address, symbols.describe(b.address), b.hits)));
+            let watchpoints = self.runner
+                .watchpoints()
+                .list()
+                .map(|w| (w.enabled, format!("W {:06X} {} {} hits: {}", w.address, symbols.describe(w.address), w.access, w.hits)));
+            let lines = breakpoints
+                .chain(watchpoints)
+                .map(|(enabled, line)| {
+                    Line::from(if enabled { Span::raw(line).green() } else { Span::raw(line).dark_gray() })
+                })
+                .collect::<Vec<_>>();
+            frame.render_widget(
+                Paragraph::new(lines)
+                    .block(Block::default().title("Breakpoints").borders(Borders::ALL)),
+                left_layout[1],
+            );
 
-            if self.vertical_scroll > Machine::MAX_ADDRESS {
-                self.vertical_scroll = Machine::MAX_ADDRESS;
+
+            // Keep the last row of 16 bytes within memory
+            let max_scroll = (Machine::MAX_ADDRESS >> 4) - 15;
+            if self.vertical_scroll > max_scroll {
+                self.vertical_scroll = max_scroll;
             }
 
+            let vertical_scroll = self.vertical_scroll as usize;
+
             let mut memory = Vec::new();
             let mut found_pc = false;
             for addr in vertical_scroll..vertical_scroll + 16 {
@@ -315,9 +680,14 @@ impl TUI {
                     line.push(Span::raw(" "));
 
                     let byte_loc = (addr * 16 + i) as u32;
-                    let byte = self.runner.machine().read_byte(byte_loc);
+                    let byte = self.runner.machine().peek_byte(byte_loc).unwrap_or_default();
 
                     let mut span = Span::raw(format!("0x{:02X}", byte));
+                    if self.runner.breakpoints().get(byte_loc).is_some_and(|b| b.enabled) {
+                        span = span.on_red().black();
+                    } else if self.runner.watchpoints().list().any(|w| w.enabled && w.unit(byte_loc).is_some()) {
+                        span = span.on_magenta().black();
+                    }
                     if !found_pc {
                         let pc_loc = self.runner.machine().get_reg(&Register::PC);
 
@@ -326,6 +696,9 @@ impl TUI {
                             found_pc = true;
                         }
                     }
+                    if byte_loc == self.memory_cursor {
+                        span = span.underlined().bold();
+                    }
                     line.push(span);
                 }
                 memory.push(Line::from(line));
@@ -342,72 +715,259 @@ impl TUI {
                 .begin_symbol(Some("↑"))
                 .end_symbol(Some("↓"));
 
-            frame.render_widget(paragraph, main_layout[1]);
+            let memory_layout = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints(vec![
+                    Constraint::Length(16),  // memory
+                    Constraint::Min(3),  // disassembly
+                ])
+                .split(main_layout[1]);
+
+            frame.render_widget(paragraph, memory_layout[0]);
             frame.render_stateful_widget(scrollbar,
-                                         main_layout[1].inner(&Margin {
+                                         memory_layout[0].inner(&Margin {
                                              vertical: 1,
                                              horizontal: 0,
                                          }), // using a inner vertical margin of 1 unit makes the scrollbar inside the block
                                          &mut scrollbar_state);
 
 
-            // Text display (runner_layout[2])
-            let display = Canvas::default()
-                .block(Block::default().title("Text Display").borders(Borders::ALL))
-                .x_bounds([0.0, 200.0])
-                .y_bounds([0.0, 25.0])
-                .paint(|ctx| {
-                    ctx.draw(&Rectangle {
-                        x: 10.0,
-                        y: 20.0,
-                        width: 10.0,
-                        height: 10.0,
-                        color: Color::Red,
-                    });
-                });
+            // Disassembly around PC, decoded from the start of its section
+            let pc = self.runner.machine().get_reg(&Register::PC);
+            let start = self.runner
+                .section_at(pc)
+                .map(|section| section.address)
+                .filter(|start| pc - start <= 0x1000)
+                .unwrap_or(pc);
+            let source = self.runner.section_at(pc).and_then(|section| section.source.as_ref());
+            let code_layout = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints(match source {
+                    Some(_) => vec![Constraint::Percentage(50), Constraint::Percentage(50)],
+                    None => vec![Constraint::Percentage(100)],
+                })
+                .split(memory_layout[1]);
+            let rows = code_layout[0].height.saturating_sub(2) as usize;
+            let disassembly = disassemble_around(self.runner.machine(), self.runner.symbols(), start, pc, rows / 3, rows - rows / 3)
+                .into_iter()
+                .map(|line| {
+                    let bytes = line.bytes.iter().map(|b| format!("{b:02X}")).collect::<String>();
+                    let breakpoint = self.runner.breakpoints().get(line.address).is_some_and(|b| b.enabled);
+                    let marker = if breakpoint { Span::raw("● ").red() } else { Span::raw("  ") };
+                    let label = line.label.as_deref().unwrap_or("");
+                    let mut text = format!("{:06X}  {bytes:<8}  {label:<8} {:<20}", line.address, line.text);
+                    if line.address == pc {
+                        // Operand address after indexing and indirection, with the current registers
+                        if let Some(effective) = effective_address(self.runner.machine(), pc) {
+                            text.push_str(&format!(" EA={effective:06X}"));
+                            if self.runner.symbols().nearest(effective).is_some() {
+                                text.push_str(&format!(" ({})", self.runner.symbols().describe(effective)));
+                            }
+                        }
+                    }
+                    let text = Span::raw(text.trim_end().to_string());
+                    Line::from(vec![marker, if line.address == pc { text.on_light_green().black() } else { text.green() }])
+                })
+                .collect::<Vec<_>>();
+            frame.render_widget(
+                Paragraph::new(disassembly)
+                    .block(Block::default().title("Disassembly").borders(Borders::ALL)),
+                code_layout[0],
+            );
+
+            // Source of the section at PC, with the statement at PC highlighted
+            if let Some(source) = source {
+                let current = source.line_at(pc).map(|line| line.line);
+                let rows = code_layout[1].height.saturating_sub(2) as usize;
+                let first = current.unwrap_or(1).saturating_sub(rows / 3).max(1);
+                let lines = (first..first + rows)
+                    .filter_map(|number| Some((number, source.text(number)?)))
+                    .map(|(number, text)| {
+                        let breakpoint = source
+                            .line(number)
+                            .is_some_and(|line| self.runner.breakpoints().get(line.address).is_some_and(|b| b.enabled));
+                        let marker = if breakpoint { Span::raw("● ").red() } else { Span::raw("  ") };
+                        let text = Span::raw(format!("{number:>5}  {text}"));
+                        Line::from(vec![marker, if Some(number) == current { text.on_light_green().black() } else { text.green() }])
+                    })
+                    .collect::<Vec<_>>();
+                let title = match source.path.as_ref().and_then(|path| path.file_name()) {
+                    Some(name) => format!("Source ({})", name.to_string_lossy()),
+                    None => "Source".to_string(),
+                };
+                frame.render_widget(
+                    Paragraph::new(lines).block(Block::default().title(title).borders(Borders::ALL)),
+                    code_layout[1],
+                );
+            }
+
+
+            let display_layout = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints(vec![
+                    Constraint::Min(3),  // framebuffer or text screen
+                    Constraint::Length(10),  // consoles
+                ])
+                .split(main_layout[2]);
+
+            // Framebuffer or text screen mapped to memory
+            let image = if self.show_framebuffer { self.runner.frame() } else { None };
+            match (self.runner.framebuffer(), image) {
+                (Some(framebuffer), Some(image)) => {
+                    let mut colors = BTreeMap::<[u8; 3], Vec<(f64, f64)>>::new();
+                    for (index, color) in image.pixels.iter().enumerate() {
+                        let (x, y) = (index as u32 % image.width, index as u32 / image.width);
+                        colors.entry(*color).or_default().push((x as f64 + 0.5, (image.height - y) as f64 - 0.5));
+                    }
+                    let title = format!("Framebuffer ({:06X}, {}x{}, {}-bit)",
+                                        framebuffer.base, image.width, image.height, framebuffer.format.bits());
+                    let display = Canvas::default()
+                        .block(Block::default().title(title).borders(Borders::ALL))
+                        .marker(Marker::HalfBlock)
+                        .x_bounds([0.0, image.width as f64])
+                        .y_bounds([0.0, image.height as f64])
+                        .paint(|ctx| {
+                            for (&[r, g, b], coords) in &colors {
+                                ctx.draw(&Points { coords, color: Color::Rgb(r, g, b) });
+                            }
+                        });
+                    frame.render_widget(display, display_layout[0]);
+                }
+                _ => {
+                    let (title, lines) = match (self.runner.text_screen(), self.runner.screen_lines()) {
+                        (Some(screen), Some(lines)) => (format!("Text Display ({:06X})", screen.base), lines),
+                        _ => ("Text Display".to_string(), Vec::new()),
+                    };
+                    let display = Paragraph::new(lines.into_iter().map(Line::from).collect::<Vec<_>>())
+                        .block(Block::default().title(title).borders(Borders::ALL));
+                    frame.render_widget(display, display_layout[0]);
+                }
+            }
 
-            frame.render_widget(display, main_layout[2]);
 
+            // Output of devices 1 and 2
+            let console_layout = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints(vec![Constraint::Percentage(50), Constraint::Percentage(50)])
+                .split(display_layout[1]);
+            for (index, pane) in self.consoles.iter_mut().enumerate() {
+                let area = console_layout[index];
+                let lines = if pane.hex { pane.buffer.hex_lines(Self::CONSOLE_HEX_WIDTH) } else { pane.buffer.text_lines() };
+                let rows = area.height.saturating_sub(2) as usize;
+                pane.scroll = pane.scroll.min(lines.len().saturating_sub(rows));
+                let end = lines.len() - pane.scroll;
+                let output = lines[end.saturating_sub(rows)..end]
+                    .iter()
+                    .map(|line| Line::from(line.as_str()))
+                    .collect::<Vec<_>>();
 
-            if let Err(e) = &self.load_result {
+                let mut title = pane.name();
+                if pane.hex {
+                    title.push_str(" (hex)");
+                }
+                if pane.scroll > 0 {
+                    title.push_str(&format!(" ↑{}", pane.scroll));
+                }
+                let title = if index == self.console_focus { Span::raw(title).yellow() } else { Span::raw(title) };
+                frame.render_widget(
+                    Paragraph::new(output).block(Block::default().title(title).borders(Borders::ALL)),
+                    area,
+                );
+            }
+
+
+            if let Some(input) = &self.goto_input {
+                frame.render_widget(
+                    Paragraph::new(format!("Go to (symbol, symbol+offset or hex address): {input}_"))
+                        .on_blue()
+                        .white()
+                        .block(Block::new().borders(Borders::ALL)),
+                    layout[3]);
+            } else if self.typing {
+                frame.render_widget(
+                    Paragraph::new(format!("Typing to the keyboard (device 0), {} keys waiting. Press Esc to stop.", self.keys.len()))
+                        .on_blue()
+                        .white()
+                        .block(Block::new().borders(Borders::ALL)),
+                    layout[3]);
+            } else if let Err(e) = &self.load_result {
                 // Print error to screen
                 frame.render_widget(
-                    Paragraph::new(e.as_str())
+                    Paragraph::new(e.to_string())
                         .on_red()
                         .black()
                         .block(Block::new().borders(Borders::ALL)),
                     layout[3]);
-            } else if let Some(Err(e)) = step {
-                // Stop running
-                self.mode = Mode::STEP;
-                self.load_result = Err(e);
+            } else if let Some(address) = self.runner.breakpoint_hit() {
+                frame.render_widget(
+                    Paragraph::new(format!("Breakpoint at {address:06X}."))
+                        .on_yellow()
+                        .black()
+                        .block(Block::new().borders(Borders::ALL)),
+                    layout[3]);
+            } else if let Some(hit) = self.runner.watch_hits().first() {
+                frame.render_widget(
+                    Paragraph::new(format!("{hit}."))
+                        .on_yellow()
+                        .black()
+                        .block(Block::new().borders(Borders::ALL)),
+                    layout[3]);
+            } else if self.runner.returned() {
+                frame.render_widget(
+                    Paragraph::new(format!("Returned from subroutine to {}.", self.runner.symbols().describe(pc)))
+                        .on_yellow()
+                        .black()
+                        .block(Block::new().borders(Borders::ALL)),
+                    layout[3]);
+            } else if let Some(reason) = self.runner.halted() {
+                frame.render_widget(
+                    Paragraph::new(format!("Program halted after {} steps: {reason}.", self.runner.steps()))
+                        .on_yellow()
+                        .black()
+                        .block(Block::new().borders(Borders::ALL)),
+                    layout[3]);
+            } else if let Some(notice) = &self.notice {
+                if step.is_some() {
+                    self.notice = None;
+                } else {
+                    frame.render_widget(
+                        Paragraph::new(notice.as_str())
+                            .on_green()
+                            .black()
+                            .block(Block::new().borders(Borders::ALL)),
+                        layout[3]);
+                }
             };
 
 
             if self.mode == Mode::LoadFile {
+                let load_address = if self.load_address.is_empty() {
+                    "from H record".to_string()
+                } else {
+                    self.load_address.clone()
+                };
                 let popup_block = Block::default()
-                    .title("Choose file:")
+                    .title(format!("Choose file (space marks files to link, type hex load address: {load_address}):"))
                     .borders(Borders::NONE)
                     .on_dark_gray();
 
                 let area = TUI::popup(area);
 
                 // Draw files in directory into area
-                let files = std::fs::read_dir("./").unwrap();
-                let mut files = files.map(|f| f.unwrap().path().display().to_string()).collect::<Vec<_>>();
+                let mut files = std::fs::read_dir("./")
+                    .into_iter()
+                    .flatten()
+                    .flatten()
+                    .map(|f| f.path().display().to_string())
+                    .collect::<Vec<_>>();
                 files.sort();
 
-                if self.selected_file.is_none() {
-                    self.selected_file = Some((files[0].clone(), 0));
-                } else if let Some((_, ix)) = &self.selected_file {
-                    // Highlight selected file
-                    let file = files.get(*ix as usize);
-                    if let Some(file) = file {
-                        self.selected_file = Some((file.clone(), *ix));
-                    } else {
-                        self.selected_file = Some((files[0].clone(), 0));
-                    }
-                }
+                let ix = self.selected_file.as_ref().map_or(0, |(_, ix)| *ix);
+                // Highlight selected file
+                self.selected_file = match files.get(ix as usize) {
+                    Some(file) => Some((file.clone(), ix)),
+                    None => files.first().map(|file| (file.clone(), 0)),
+                };
 
                 let files = files.iter().enumerate().map(|(ix, f)| {
                     let mut style = Style::default().bg(Color::DarkGray);
@@ -416,7 +976,8 @@ impl TUI {
                             style = style.fg(Color::Black);
                         }
                     }
-                    Line::from(Span::styled(f.as_str(), style))
+                    let mark = if self.marked_files.contains(f) { "* " } else { "  " };
+                    Line::from(Span::styled(format!("{mark}{f}"), style))
                 }).collect::<Vec<_>>();
 
                 // Render files
@@ -429,6 +990,28 @@ impl TUI {
                 frame.render_widget(files, area);
 
                 frame.render_widget(popup_block, area);
+            } else if self.show_load_map {
+                let load_map = match self.runner.load_map() {
+                    Some(map) => map.to_string(),
+                    None => "No program loaded.".to_string(),
+                };
+
+                frame.render_widget(
+                    Paragraph::new(load_map)
+                        .block(Block::default().title("Load map (m to close)").borders(Borders::ALL))
+                        .on_dark_gray(),
+                    TUI::popup(area));
+            } else if self.show_profile {
+                let report = match self.runner.profile_report(Self::PROFILE_TOP) {
+                    Some(report) => report.to_string(),
+                    None => "Profiling is off.".to_string(),
+                };
+
+                frame.render_widget(
+                    Paragraph::new(report)
+                        .block(Block::default().title("Profile (p to close)").borders(Borders::ALL))
+                        .on_dark_gray(),
+                    TUI::popup(area));
             }
         })
     }